@@ -0,0 +1,259 @@
+//! The bank lets agents deposit cash for interest and take loans against their
+//! expected income. The bank can be found in every market building.
+
+use crate::{
+    config::Config,
+    entity::{agent::Agent, Entity, EntityType},
+};
+
+/// A loan an agent took from the bank, it is repaid in daily installments.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct Loan {
+    /// The amount still owed, including interest.
+    pub owed: u32,
+    /// The amount that has to be paid every day.
+    pub installment: u32,
+    /// The amount of installments missed in a row.
+    pub missed: u32,
+}
+
+/// The bank is not an entity in the world, the accounts of the agents are
+/// saved in the agents themselves.
+#[derive(Debug, Clone, Default)]
+pub struct Bank {
+    /// Cash held by the bank. This goes negative when the bank lends out more
+    /// than it holds, we assume the bank can always create money.
+    pub reserves: i64,
+    /// Total amount of loans the agents did not repay.
+    pub written_off: u64,
+    /// Count of defaulted loans.
+    pub defaults: u32,
+}
+
+impl Bank {
+    /// Move cash from an agent into its deposit.
+    pub fn deposit(&mut self, a: &mut Agent, amount: u32) {
+        a.cash = a.cash.checked_sub(amount).unwrap();
+        a.deposit += amount;
+        self.reserves += amount as i64;
+    }
+
+    /// Move cash from the deposit of an agent back to its cash.
+    pub fn withdraw(&mut self, a: &mut Agent, amount: u32) {
+        a.deposit = a.deposit.checked_sub(amount).unwrap();
+        a.cash += amount;
+        self.reserves -= amount as i64;
+    }
+
+    /// Lend the given amount to an agent. Interest is added up front, and the
+    /// total is split evenly over the loan term.
+    pub fn lend(&mut self, a: &mut Agent, amount: u32, config: &Config) {
+        assert!(a.loan.is_none());
+        let owed = (amount as f32 * (1. + config.loan_interest * config.loan_term as f32)) as u32;
+        let installment = owed.div_ceil(config.loan_term);
+        a.loan = Some(Loan {
+            owed,
+            installment,
+            missed: 0,
+        });
+        a.cash += amount;
+        self.reserves -= amount as i64;
+    }
+
+    /// This should be called once per day. Pay interest on the deposits,
+    /// collect the installments of the loans and handle defaults.
+    pub fn step_day(&mut self, entities: &mut [Entity], config: &Config) {
+        for e in entities {
+            if let EntityType::Agent(a) = &mut e.ty {
+                // Dead agents can not repay their loans.
+                if a.dead {
+                    if a.loan.is_some() {
+                        self.write_off(a);
+                    }
+                    continue;
+                }
+
+                // Update the expected income, the bank lends against this.
+                a.expected_income = (a.expected_income as f32
+                    * (1. - config.expected_income_update)
+                    + a.income as f32 * config.expected_income_update)
+                    as u32;
+
+                let interest = (a.deposit as f32 * config.deposit_interest) as u32;
+                a.deposit += interest;
+                self.reserves -= interest as i64;
+
+                self.collect_installment(a, config);
+            }
+        }
+    }
+
+    /// Collect the installment of the loan of an agent, first from cash and
+    /// then from its deposit.
+    fn collect_installment(&mut self, a: &mut Agent, config: &Config) {
+        let loan = match &mut a.loan {
+            Some(loan) => loan,
+            None => return,
+        };
+
+        let payment = loan.installment.min(loan.owed);
+        if a.cash + a.deposit < payment {
+            loan.missed += 1;
+            if loan.missed > config.max_missed_payments {
+                self.write_off(a);
+            }
+            return;
+        }
+
+        let from_cash = payment.min(a.cash);
+        a.cash -= from_cash;
+        a.deposit -= payment - from_cash;
+        self.reserves += payment as i64;
+
+        loan.owed -= payment;
+        loan.missed = 0;
+        if loan.owed == 0 {
+            a.loan = None;
+        }
+    }
+
+    /// Write off the loan of an agent, the bank seizes the deposit and the
+    /// agent will never get a loan again.
+    fn write_off(&mut self, a: &mut Agent) {
+        if let Some(loan) = a.loan.take() {
+            let seized = a.deposit.min(loan.owed);
+            a.deposit -= seized;
+            self.reserves += seized as i64;
+            self.written_off += (loan.owed - seized) as u64;
+            self.defaults += 1;
+            a.defaulted = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::Pos;
+
+    fn config() -> Config {
+        Config {
+            deposit_interest: 0.,
+            loan_interest: 0.01,
+            loan_term: 10,
+            max_missed_payments: 1,
+            ..Config::default()
+        }
+    }
+
+    fn agent(cash: u32, config: &Config) -> Agent {
+        let mut a = Agent::new(config);
+        a.cash = cash;
+        a.deposit = 0;
+        a
+    }
+
+    fn entity(a: Agent) -> Entity {
+        Entity {
+            pos: Pos::default(),
+            ty: EntityType::Agent(Box::new(a)),
+        }
+    }
+
+    fn agent_of(e: &Entity) -> &Agent {
+        match &e.ty {
+            EntityType::Agent(a) => a,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn deposit_and_withdraw() {
+        let config = config();
+        let mut bank = Bank::default();
+        let mut a = agent(100, &config);
+        bank.deposit(&mut a, 60);
+        assert_eq!((a.cash, a.deposit, bank.reserves), (40, 60, 60));
+        bank.withdraw(&mut a, 20);
+        assert_eq!((a.cash, a.deposit, bank.reserves), (60, 40, 40));
+    }
+
+    #[test]
+    fn loan_is_repaid_in_installments() {
+        let config = config();
+        let mut bank = Bank::default();
+        let mut a = agent(0, &config);
+        bank.lend(&mut a, 1000, &config);
+        assert_eq!(a.cash, 1000);
+        assert_eq!(bank.reserves, -1000);
+        assert_eq!(
+            a.loan,
+            Some(Loan {
+                owed: 1100,
+                installment: 110,
+                missed: 0,
+            })
+        );
+
+        // The installment is taken from cash first, then from the deposit.
+        a.cash = 50;
+        a.deposit = 100;
+        let mut entities = vec![entity(a)];
+        bank.step_day(&mut entities, &config);
+        let a = agent_of(&entities[0]);
+        assert_eq!((a.cash, a.deposit), (0, 40));
+        assert_eq!(a.loan.as_ref().map(|l| l.owed), Some(990));
+        assert_eq!(bank.reserves, -890);
+
+        // The last installment clears the loan.
+        if let EntityType::Agent(a) = &mut entities[0].ty {
+            a.cash = 1000;
+            a.loan.as_mut().unwrap().owed = 30;
+        }
+        bank.step_day(&mut entities, &config);
+        let a = agent_of(&entities[0]);
+        assert_eq!(a.cash, 970);
+        assert!(a.loan.is_none());
+    }
+
+    #[test]
+    fn missed_payments_default() {
+        let config = config();
+        let mut bank = Bank::default();
+        let mut a = agent(0, &config);
+        bank.lend(&mut a, 1000, &config);
+        a.cash = 0;
+        a.deposit = 100;
+        let mut entities = vec![entity(a)];
+
+        // One missed payment is allowed, the second one defaults.
+        bank.step_day(&mut entities, &config);
+        let a = agent_of(&entities[0]);
+        assert_eq!(a.loan.as_ref().map(|l| l.missed), Some(1));
+        assert!(!a.defaulted);
+
+        bank.step_day(&mut entities, &config);
+        let a = agent_of(&entities[0]);
+        assert!(a.loan.is_none());
+        assert!(a.defaulted);
+        assert_eq!(a.deposit, 0);
+        assert_eq!(bank.written_off, 1000);
+        assert_eq!(bank.defaults, 1);
+        assert_eq!(bank.reserves, -900);
+    }
+
+    #[test]
+    fn dead_agents_default() {
+        let config = config();
+        let mut bank = Bank::default();
+        let mut a = agent(0, &config);
+        bank.lend(&mut a, 100, &config);
+        a.dead = true;
+        let mut entities = vec![entity(a)];
+        bank.step_day(&mut entities, &config);
+        let a = agent_of(&entities[0]);
+        assert!(a.loan.is_none());
+        assert_eq!(bank.defaults, 1);
+        assert_eq!(bank.written_off, 110);
+    }
+}
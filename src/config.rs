@@ -1,8 +1,30 @@
+use std::path::{Path, PathBuf};
+
+use rand::{
+    distributions::{Distribution as _, WeightedIndex},
+    prelude::*,
+};
+use rand_distr::{LogNormal, Normal};
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    entity::{
+        brain::{BrainKind, BrainShare},
+        construction::Blueprint,
+        crafting::Recipe,
+        learning::{JobLearning, JobScoring},
+        resources::ResourceConfig,
+    },
+    government::Redistribution,
+    market::MarketClearing,
+    price_index::PriceIndexKind,
+};
+
 /// This config defines all the parameters of a simulation, the repetitions in
-/// batch mode and for how many ticks the simulation should last.
+/// batch mode and for how many ticks the simulation should last. Missing keys
+/// take their default value.
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(default)]
 pub struct Config {
     /// Under what altitude should we choose the ocean biome
     pub ocean_cutoff: isize,
@@ -10,8 +32,9 @@ pub struct Config {
     pub beach_cutoff: isize,
     /// Time in the the agents will leave their job and the market to go home.
     pub closing_time: u32,
-    /// At this energy an agent will stop doing its job and go home to eat.
-    pub critical_energy: u32,
+    /// At this energy an agent will stop doing its job and go home to eat,
+    /// drawn for every agent.
+    pub critical_energy: Distribution,
     /// Length of one day, with this value numerous other values also have to
     /// change.
     pub day_length: u32,
@@ -22,24 +45,60 @@ pub struct Config {
     /// The time in ticks agents will explore their surroundings before deciding
     /// what job to take, when they are an explorer.
     pub exploration_timeout: u16,
-    /// Mean of the greed distribution.
-    pub greed_mean: f32,
-    /// Standard deviation of the greed distribution.
-    pub greed_sd: f32,
-    /// Initial cash of the agents
-    pub initial_cash: u32,
-    /// Initial energy of the agents
-    pub initial_energy: u32,
-    /// Initial resources of the agents, this will be the same for all 4 resources.
+    /// Distribution of the greed of the initial agents, negative values are
+    /// clipped to zero.
+    pub greed: Distribution,
+    /// Distribution of the initial cash of the agents
+    pub initial_cash: Distribution,
+    /// Distribution of the initial energy of the agents, agents try to keep
+    /// at least this much energy.
+    pub initial_energy: Distribution,
+    /// Initial resources of the agents, this will be the same for all resources.
     pub initial_inventory: u32,
-    /// The initial nutritional value of all resources per agent.
-    pub initial_nutrition: u8,
-    /// The update rate of the market price moving average.
+    /// The kinds of resources in the world, with their initial nutritional
+    /// value, the biomes they spawn in and the job that harvests them.
+    pub resources: Vec<ResourceConfig>,
+    /// How the market matches purchases with orders, either continuously or
+    /// once per day at the closing time.
+    pub market_clearing: MarketClearing,
+    /// In a call auction agents bid at most this multiple of the market price
+    /// per unit, or of the cheapest order before the first trade.
+    pub bid_markup: f32,
+    /// The price index used to compute the market price from the trades.
+    pub price_index: PriceIndexKind,
+    /// The update rate of the market price moving average, used by the Ema
+    /// price index.
     pub market_price_update: f32,
+    /// If true every market building has its own order book and market price,
+    /// otherwise all market buildings share one global market.
+    pub local_markets: bool,
+    /// When choosing between local markets, how many tiles of walking one unit
+    /// of the remembered basket price is worth.
+    pub market_price_weight: f32,
+    /// When choosing between local markets, how many tiles of walking one unit
+    /// of the remembered amount offered on the market is worth.
+    pub market_depth_weight: f32,
+    /// When choosing between local markets, how many tiles of walking the
+    /// agents accept to go back to the market they last traded at.
+    pub market_loyalty: f32,
     /// Maximum energy of the agents.
     pub max_energy: u32,
-    /// Energy cost per step per agent. This will be subtracted every step.
-    pub energy_cost: u32,
+    /// Energy cost per step of an agent walking.
+    pub move_energy_cost: i32,
+    /// Energy cost per step of an agent farming, fishing or crafting.
+    pub work_energy_cost: i32,
+    /// Energy cost per step of an agent moving in a boat.
+    pub sail_energy_cost: i32,
+    /// Energy cost per step of an agent waiting, for example on the market.
+    pub idle_energy_cost: i32,
+    /// Energy cost per step of an agent at home during the day.
+    pub home_energy_cost: i32,
+    /// Energy cost per step of an agent at home after the closing time. A
+    /// negative cost regenerates energy while resting.
+    pub rest_energy_cost: i32,
+    /// If true agents stay at home after the closing time until the next
+    /// day, otherwise they leave again once they have eaten.
+    pub night_rest: bool,
     /// When an agent eats a resource, this will be added to the nutritional
     /// values of the resources that the agent did not just eat.
     pub nutrition_add: u8,
@@ -57,15 +116,169 @@ pub struct Config {
     pub explorer_fish_points: u32,
     /// When exploring, what should we divide the amount of resources by to get the value.
     pub explorer_resource_divisor: u32,
+    /// How explorers score the jobs, by the resources they see or by the
+    /// expected income.
+    pub explorer_scoring: JobScoring,
     /// How long until resources respawn (ticks).
     pub resource_timeout: u16,
     /// When agents search for something, how big is the search radius. Note
     /// that we have O(n^2) complexity.
     pub search_radius: usize,
+    /// If true agents remember the resources, markets and boats they have
+    /// seen, and plan from their memory when nothing is in sight.
+    pub memory: bool,
+    /// How far agents can see when they update their memory.
+    pub vision_radius: usize,
+    /// The maximum amount of resources an agent can remember.
+    pub memory_capacity: usize,
+    /// If true agents next to each other, or in the same market, exchange
+    /// what they remember. This only has an effect if memory is enabled.
+    pub gossip: bool,
+    /// The chance per tick that two agents next to each other exchange their
+    /// memory.
+    pub gossip_chance: f32,
+    /// The chance that each remembered resource or market is passed on when
+    /// agents exchange their memory.
+    pub gossip_fidelity: f32,
     /// Timeout duration until agents become explorers to reevaluate their job if they are unsuccessful.
     pub timeout_quota: u16,
+    /// How agents select a new job when their quota times out, either by
+    /// exploring or by learning the value of every job.
+    pub job_learning: JobLearning,
+    /// The update rate of the learned job values.
+    pub learning_rate: f32,
+    /// How much cash one unit of energy gained by eating is worth in the
+    /// reward of the job learning.
+    pub learning_energy_weight: f32,
     /// Chance of an agent not walking in a random direction to not get stuck.
     pub unstuckifier_chance: f64,
+    /// Fraction of every sale that is taken as tax from the seller.
+    pub sales_tax: f32,
+    /// Fraction of the daily income (cash earned by selling) that is taxed at
+    /// the end of every day.
+    pub income_tax: f32,
+    /// Fraction of the cash of every agent that is taxed at the end of every
+    /// day.
+    pub wealth_tax: f32,
+    /// What the government does with the collected taxes.
+    pub redistribution: Redistribution,
+    /// Maximum amount of food a starving agent gets from the granary per day.
+    pub granary_ration: u32,
+    /// If true, agents can deposit cash and take loans at the bank in every
+    /// market.
+    pub bank: bool,
+    /// Daily interest rate paid on deposits.
+    pub deposit_interest: f32,
+    /// Daily interest rate of loans, the interest is added when the loan is
+    /// taken.
+    pub loan_interest: f32,
+    /// Amount of days over which a loan is repaid.
+    pub loan_term: u32,
+    /// Maximum fraction of the expected daily income that can go to loan
+    /// installments. This determines how much an agent can borrow.
+    pub loan_income_share: f32,
+    /// Amount of installments an agent can miss in a row before defaulting.
+    pub max_missed_payments: u32,
+    /// The update rate of the moving average of the daily income of the
+    /// agents.
+    pub expected_income_update: f32,
+    /// Which brains the agents get, each brain is assigned to a fraction of the
+    /// agents.
+    pub brains: Vec<BrainShare>,
+    /// If true agents with enough energy and cash have children.
+    pub births: bool,
+    /// The minimum energy an agent needs to have a child.
+    pub birth_energy: u32,
+    /// The minimum cash an agent needs to have a child.
+    pub birth_cash: u32,
+    /// Standard deviation of the mutation of the greed a child inherits from
+    /// its parent.
+    pub greed_mutation_sd: f32,
+    /// The energy it costs an agent to have a child.
+    pub birth_cost: u32,
+    /// Fraction of the cash and inventory of the parent that is given to the
+    /// child.
+    pub birth_share: f32,
+    /// If true agents age, this makes them less productive when they are young
+    /// or old, and lets them die of old age.
+    pub aging: bool,
+    /// Maximum age of an agent in days. The initial agents get a random age up
+    /// to half the maximum age.
+    pub max_age: u32,
+    /// The chance an agent dies per day depending on its age in days.
+    pub mortality: Curve,
+    /// Multiplier of the energy cost depending on the age in days.
+    pub age_energy_cost: Curve,
+    /// Chance that farming succeeds depending on the age in days.
+    pub age_yield: Curve,
+    /// Maximum amount of agents living in one hut. The huts of the initial
+    /// agents get a random amount of residents up to this size, and children
+    /// stay with their parent until the hut is full.
+    pub household_size: u32,
+    /// If true the residents of a hut also share their cash.
+    pub household_pool_cash: bool,
+    /// If true the residents of a hut avoid doing the same job.
+    pub household_jobs: bool,
+    /// If true agents have a skill for every job, which determines the chance
+    /// that farming succeeds.
+    pub skills: bool,
+    /// The skill of the initial agents and children for every job.
+    pub initial_skill: f32,
+    /// The chance that farming succeeds with skill zero.
+    pub unskilled_yield: f32,
+    /// Fraction of the missing skill gained with every successful farm action.
+    pub skill_gain: f32,
+    /// Fraction of the skill of every job, except the current one, that is
+    /// lost every tick.
+    pub skill_decay: f32,
+    /// The recipes crafters use to turn resources into other resources, the
+    /// crafter job is only available if there are recipes. Recipes that make
+    /// meals are used by cooks, the cook job is only available if there are
+    /// meal recipes.
+    pub recipes: Vec<Recipe>,
+    /// Fraction of the initial agents that start as crafters.
+    pub crafters: f32,
+    /// Fraction of the initial agents that start as cooks.
+    pub cooks: f32,
+    /// How many crafts worth of inputs a crafter or cook buys at once.
+    pub craft_batches: u32,
+    /// If true agents cook meals at home from the food they bought, when that
+    /// is cheaper than buying the meals or raw food.
+    pub home_cooking: bool,
+    /// If true fishers can only use the boats of their household and the
+    /// boats that belong to nobody. Boats put on the beach belong to the
+    /// household of the agent that put them there and can not be sold again.
+    pub boat_ownership: bool,
+    /// Maximum amount of resources caught on one trip with a boat.
+    pub boat_capacity: u32,
+    /// How many trips a boat lasts, zero if boats never wear out.
+    pub boat_durability: u32,
+    /// The buildings households can construct, and what they cost. Households
+    /// construct a hut when they are full and children are born, a market
+    /// when there is none close to their hut, and fishers a boat when they
+    /// have none. With a hut blueprint children are only born into existing
+    /// huts.
+    pub blueprints: Vec<Blueprint>,
+    /// Households construct a market if there is none within this distance
+    /// of their hut, zero to never construct markets.
+    pub market_distance: usize,
+    /// If true agents move to an empty or new hut close to where they work,
+    /// when they remember no resource of their job close to their hut, or
+    /// when they failed to reach their cash quota too often.
+    pub migration: bool,
+    /// Agents migrate if no resource of their job is within this distance of
+    /// their hut.
+    pub migration_distance: usize,
+    /// Agents migrate after failing to reach their cash quota this many times
+    /// in a row, zero to never migrate because of failures.
+    pub migration_failures: u32,
+    /// If true agents can only carry a limited amount of goods, and store the
+    /// rest in their hut.
+    pub storage: bool,
+    /// Maximum amount of goods an agent carries, if storage is enabled.
+    pub carry_capacity: u32,
+    /// Maximum amount of goods stored in one hut, if storage is enabled.
+    pub storage_capacity: u32,
     /// Total amount of ticks to run the simulation for in batch mode.
     pub batch_total_step_count: u32,
     /// Total amount of repetitions to repeat the simulation for in batch mode.
@@ -79,20 +292,32 @@ impl Default for Config {
             ocean_cutoff: -300,
             beach_cutoff: -250,
             closing_time: (DAY_LENGTH * 3) / 4,
-            critical_energy: 500,
+            critical_energy: Distribution::Fixed(500.),
             day_length: DAY_LENGTH,
             default_exp: DAY_LENGTH * 10,
             default_rval: DAY_LENGTH * 3,
             exploration_timeout: 500,
-            greed_mean: 5.,
-            greed_sd: 10.,
-            initial_cash: 20000,
-            initial_energy: 5000,
+            greed: Distribution::Normal { mean: 5., sd: 10. },
+            initial_cash: Distribution::Fixed(20000.),
+            initial_energy: Distribution::Fixed(5000.),
             initial_inventory: 0,
-            initial_nutrition: 100,
+            resources: ResourceConfig::defaults(),
+            market_clearing: MarketClearing::Continuous,
+            bid_markup: 2.,
+            price_index: PriceIndexKind::Ema,
             market_price_update: 0.01,
+            local_markets: false,
+            market_price_weight: 0.5,
+            market_depth_weight: 0.05,
+            market_loyalty: 0.,
             max_energy: 10000,
-            energy_cost: 2,
+            move_energy_cost: 2,
+            work_energy_cost: 2,
+            sail_energy_cost: 2,
+            idle_energy_cost: 2,
+            home_energy_cost: 2,
+            rest_energy_cost: 2,
+            night_rest: false,
             nutrition_add: 4,
             nutrition_sub: 9,
             order_price_decay: 75,
@@ -101,11 +326,521 @@ impl Default for Config {
             resource_timeout: DAY_LENGTH as u16 * 10,
             explorer_fish_points: 50,
             explorer_resource_divisor: 10,
+            explorer_scoring: JobScoring::Observations,
             search_radius: 15,
+            memory: false,
+            vision_radius: 5,
+            memory_capacity: 50,
+            gossip: false,
+            gossip_chance: 0.1,
+            gossip_fidelity: 0.5,
             timeout_quota: DAY_LENGTH as u16 * 10,
+            job_learning: JobLearning::Explorer,
+            learning_rate: 0.1,
+            learning_energy_weight: 0.01,
             unstuckifier_chance: 0.75,
+            sales_tax: 0.,
+            income_tax: 0.,
+            wealth_tax: 0.,
+            redistribution: Redistribution::None,
+            granary_ration: 10,
+            bank: false,
+            deposit_interest: 0.001,
+            loan_interest: 0.002,
+            loan_term: 30,
+            loan_income_share: 0.5,
+            max_missed_payments: 5,
+            expected_income_update: 0.1,
+            brains: vec![BrainShare {
+                brain: BrainKind::Default,
+                fraction: 1.,
+            }],
+            births: false,
+            birth_energy: 5000,
+            birth_cash: 5000,
+            greed_mutation_sd: 1.,
+            birth_cost: 1000,
+            birth_share: 0.5,
+            aging: false,
+            max_age: 120,
+            mortality: Curve(vec![(0., 0.), (60., 0.), (120., 0.1)]),
+            age_energy_cost: Curve(vec![(0., 1.), (60., 1.), (120., 1.5)]),
+            age_yield: Curve(vec![(0., 0.5), (10., 1.), (60., 1.), (120., 0.5)]),
+            household_size: 1,
+            household_pool_cash: false,
+            household_jobs: false,
+            skills: false,
+            initial_skill: 0.2,
+            unskilled_yield: 0.5,
+            skill_gain: 0.01,
+            skill_decay: 0.0005,
+            recipes: Vec::new(),
+            crafters: 0.2,
+            cooks: 0.1,
+            craft_batches: 10,
+            home_cooking: false,
+            boat_ownership: false,
+            boat_capacity: 100,
+            boat_durability: 0,
+            blueprints: Vec::new(),
+            market_distance: 0,
+            migration: false,
+            migration_distance: 20,
+            migration_failures: 3,
+            storage: false,
+            carry_capacity: 50,
+            storage_capacity: 500,
             batch_total_step_count: DAY_LENGTH * 5000,
             repetitions: 1,
         }
     }
 }
+
+impl Config {
+    /// Load a config from a json file. Distributions read from a file are
+    /// loaded relative to the directory of the config.
+    pub fn load(path: &Path) -> std::io::Result<Config> {
+        let string = std::fs::read_to_string(path)?;
+        let mut value: serde_json::Value = serde_json::from_str(&string)?;
+        migrate(&mut value);
+        let mut config: Config = serde_json::from_value(value)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for d in [
+            &mut config.critical_energy,
+            &mut config.greed,
+            &mut config.initial_cash,
+            &mut config.initial_energy,
+        ] {
+            d.load(dir)?;
+        }
+        config.validate()?;
+        // Resources are looked up by name only once.
+        config.recipes = config
+            .recipes
+            .iter()
+            .map(|r| r.resolve(&config))
+            .collect::<std::io::Result<_>>()?;
+        config.blueprints = config
+            .blueprints
+            .iter()
+            .map(|b| b.resolve(&config))
+            .collect::<std::io::Result<_>>()?;
+        Ok(config)
+    }
+
+    /// Check that the values in the config make sense, so that a wrong config
+    /// gives a clear error instead of a panic or nonsense results later on.
+    fn validate(&self) -> std::io::Result<()> {
+        // Resources are indexed by a u8.
+        if self.resources.is_empty() || self.resources.len() > 256 {
+            return Err(invalid(format!(
+                "Between 1 and 256 resources are needed, got {}",
+                self.resources.len()
+            )));
+        }
+        if let Some(r) = self
+            .resources
+            .iter()
+            .find(|r| !r.spawns.is_empty() && r.job.is_none())
+        {
+            return Err(invalid(format!(
+                "The resource {} spawns in the world but no job harvests it",
+                r.name
+            )));
+        }
+        // Every biome resources spawn in needs a positive total weight.
+        let spawns = self.resources.iter().flat_map(|r| &r.spawns);
+        for biome in spawns.clone().map(|s| s.biome) {
+            if spawns
+                .clone()
+                .filter(|s| s.biome == biome)
+                .all(|s| s.weight == 0)
+            {
+                return Err(invalid(format!(
+                    "All resource spawn weights of the biome {:?} are zero",
+                    biome
+                )));
+            }
+        }
+        // Sellers raise their price to pay for the sales tax, which only works
+        // if some of the price is left after taxing it.
+        if !(0. ..1.).contains(&self.sales_tax) {
+            return Err(invalid(format!(
+                "sales_tax must be in [0, 1), got {}",
+                self.sales_tax
+            )));
+        }
+        if !(0. ..=1.).contains(&self.income_tax) {
+            return Err(invalid(format!(
+                "income_tax must be in [0, 1], got {}",
+                self.income_tax
+            )));
+        }
+        if !(0. ..=1.).contains(&self.wealth_tax) {
+            return Err(invalid(format!(
+                "wealth_tax must be in [0, 1], got {}",
+                self.wealth_tax
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Convert the keys of older configs to the current format. The greed used to
+/// be given by `greed_mean` and `greed_sd`, the critical energy, initial cash
+/// and initial energy used to be plain numbers, and a single `energy_cost` was
+/// paid every step whatever the agent did.
+fn migrate(value: &mut serde_json::Value) {
+    let object = match value.as_object_mut() {
+        Some(o) => o,
+        None => return,
+    };
+    let mean = object.remove("greed_mean");
+    let sd = object.remove("greed_sd");
+    if !object.contains_key("greed") {
+        if let (Some(mean), Some(sd)) = (mean, sd) {
+            object.insert(
+                "greed".to_string(),
+                serde_json::json!({ "Normal": { "mean": mean, "sd": sd } }),
+            );
+        }
+    }
+    if let Some(cost) = object.remove("energy_cost") {
+        for key in [
+            "move_energy_cost",
+            "work_energy_cost",
+            "sail_energy_cost",
+            "idle_energy_cost",
+            "home_energy_cost",
+            "rest_energy_cost",
+        ] {
+            object.entry(key).or_insert_with(|| cost.clone());
+        }
+    }
+    for key in ["critical_energy", "initial_cash", "initial_energy"] {
+        if let Some(v) = object.get_mut(key) {
+            if v.is_number() {
+                *v = serde_json::json!({ "Fixed": v.clone() });
+            }
+        }
+    }
+}
+
+/// An error for invalid data in the config.
+fn invalid(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, msg)
+}
+
+/// A distribution of a parameter that is drawn for every agent.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Distribution {
+    /// Every agent gets the same value.
+    Fixed(f32),
+    /// Uniform between min and max.
+    Uniform { min: f32, max: f32 },
+    /// Normal distribution with the given mean and standard deviation. Values
+    /// drawn as integers, like the greed, are clipped at 0.
+    Normal { mean: f32, sd: f32 },
+    /// Log-normal distribution, mu and sigma are the mean and standard
+    /// deviation of the logarithm of the value.
+    LogNormal { mu: f32, sigma: f32 },
+    /// Mixture of distributions, every component is chosen with a chance
+    /// proportional to its weight. For example two normal distributions for a
+    /// bimodal distribution.
+    Mixture(Vec<(f32, Distribution)>),
+    /// One of the given values, each with the same chance.
+    Values(Vec<f32>),
+    /// One of the values in the file, one value per line. This is replaced by
+    /// Values when the config is loaded.
+    File(PathBuf),
+}
+
+impl Distribution {
+    /// Draw a value from the distribution.
+    pub fn sample(&self) -> f32 {
+        let mut rng = thread_rng();
+        match self {
+            Distribution::Fixed(v) => *v,
+            Distribution::Uniform { min, max } => rng.gen_range(*min..=*max),
+            Distribution::Normal { mean, sd } => Normal::new(*mean, *sd)
+                .expect("Invalid normal distribution")
+                .sample(&mut rng),
+            Distribution::LogNormal { mu, sigma } => LogNormal::new(*mu, *sigma)
+                .expect("Invalid log-normal distribution")
+                .sample(&mut rng),
+            Distribution::Mixture(components) => {
+                let weights = WeightedIndex::new(components.iter().map(|(w, _)| *w))
+                    .expect("Invalid mixture weights");
+                components[weights.sample(&mut rng)].1.sample()
+            }
+            Distribution::Values(values) => *values.choose(&mut rng).expect("No values"),
+            Distribution::File(path) => panic!("Distribution file {:?} is not loaded", path),
+        }
+    }
+
+    /// Draw a value and truncate it to a non-negative integer.
+    pub fn sample_u32(&self) -> u32 {
+        self.sample().max(0.) as u32
+    }
+
+    /// Replace the file distributions by the values in the file, the path is
+    /// relative to the given directory. Parameters that can not be sampled,
+    /// like empty lists of values, are rejected.
+    fn load(&mut self, dir: &Path) -> std::io::Result<()> {
+        match self {
+            Distribution::File(path) => {
+                let string = std::fs::read_to_string(dir.join(&path))?;
+                let values = string
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty())
+                    .map(|l| {
+                        l.parse().map_err(|_| {
+                            std::io::Error::new(
+                                std::io::ErrorKind::InvalidData,
+                                format!("Invalid value {} in {:?}", l, path),
+                            )
+                        })
+                    })
+                    .collect::<std::io::Result<Vec<f32>>>()?;
+                if values.is_empty() {
+                    return Err(invalid(format!("No values in {:?}", path)));
+                }
+                *self = Distribution::Values(values);
+            }
+            Distribution::Values(values) if values.is_empty() => {
+                return Err(invalid("Empty list of values".to_string()));
+            }
+            Distribution::Uniform { min, max } if *min > *max => {
+                return Err(invalid(format!(
+                    "Uniform distribution with min {} above max {}",
+                    min, max
+                )));
+            }
+            Distribution::Normal { sd, .. } if *sd < 0. => {
+                return Err(invalid(format!(
+                    "Normal distribution with negative sd {}",
+                    sd
+                )));
+            }
+            Distribution::LogNormal { sigma, .. } if *sigma < 0. => {
+                return Err(invalid(format!(
+                    "Log-normal distribution with negative sigma {}",
+                    sigma
+                )));
+            }
+            Distribution::Mixture(components) => {
+                if components.is_empty() {
+                    return Err(invalid("Empty mixture".to_string()));
+                }
+                if components.iter().any(|(w, _)| *w < 0.)
+                    || components.iter().all(|(w, _)| *w == 0.)
+                {
+                    return Err(invalid(
+                        "Mixture weights must not be negative or all zero".to_string(),
+                    ));
+                }
+                for (_, d) in components {
+                    d.load(dir)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// A piecewise linear curve, given by a list of (x, y) points sorted by x.
+/// Outside the points the curve is constant.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Curve(pub Vec<(f32, f32)>);
+
+impl Curve {
+    /// Evaluate the curve at x, an empty curve is zero everywhere.
+    pub fn eval(&self, x: f32) -> f32 {
+        let points = &self.0;
+        match points.iter().position(|(px, _)| *px > x) {
+            None => points.last().map_or(0., |(_, y)| *y),
+            Some(0) => points[0].1,
+            Some(i) => {
+                let (x0, y0) = points[i - 1];
+                let (x1, y1) = points[i];
+                y0 + (y1 - y0) * (x - x0) / (x1 - x0)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curve_interpolates_between_points() {
+        let curve = Curve(vec![(0., 1.), (10., 0.5), (20., 0.)]);
+        assert_eq!(curve.eval(0.), 1.);
+        assert_eq!(curve.eval(5.), 0.75);
+        assert_eq!(curve.eval(10.), 0.5);
+        assert_eq!(curve.eval(15.), 0.25);
+        assert_eq!(curve.eval(20.), 0.);
+    }
+
+    #[test]
+    fn curve_is_constant_beyond_end_points() {
+        let curve = Curve(vec![(10., 2.), (20., 4.)]);
+        assert_eq!(curve.eval(-5.), 2.);
+        assert_eq!(curve.eval(25.), 4.);
+        assert_eq!(Curve(vec![(1., 3.)]).eval(0.), 3.);
+        assert_eq!(Curve(vec![(1., 3.)]).eval(2.), 3.);
+        assert_eq!(Curve(Vec::new()).eval(1.), 0.);
+    }
+
+    #[test]
+    fn distribution_loads_values_from_file() {
+        let dir = std::env::temp_dir();
+        let name = format!("atp_values_{}.txt", std::process::id());
+        std::fs::write(dir.join(&name), "1\n 2.5 \n\n3\n").unwrap();
+
+        let mut d = Distribution::Mixture(vec![(1., Distribution::File(PathBuf::from(&name)))]);
+        let result = d.load(&dir);
+        std::fs::remove_file(dir.join(&name)).unwrap();
+        result.unwrap();
+        assert_eq!(
+            d,
+            Distribution::Mixture(vec![(1., Distribution::Values(vec![1., 2.5, 3.]))])
+        );
+    }
+
+    #[test]
+    fn distribution_rejects_invalid_values() {
+        let dir = std::env::temp_dir();
+        let name = format!("atp_invalid_{}.txt", std::process::id());
+        std::fs::write(dir.join(&name), "1\nabc\n").unwrap();
+        let result = Distribution::File(PathBuf::from(&name)).load(&dir);
+        std::fs::remove_file(dir.join(&name)).unwrap();
+        assert!(result.is_err());
+
+        std::fs::write(dir.join(&name), "\n\n").unwrap();
+        let result = Distribution::File(PathBuf::from(&name)).load(&dir);
+        std::fs::remove_file(dir.join(&name)).unwrap();
+        assert!(result.is_err());
+
+        assert!(Distribution::Values(Vec::new()).load(&dir).is_err());
+        assert!(Distribution::Mixture(Vec::new()).load(&dir).is_err());
+        assert!(Distribution::Uniform { min: 2., max: 1. }
+            .load(&dir)
+            .is_err());
+        assert!(Distribution::Normal { mean: 1., sd: -1. }
+            .load(&dir)
+            .is_err());
+        assert!(Distribution::LogNormal { mu: 1., sigma: -1. }
+            .load(&dir)
+            .is_err());
+        let fixed = || Distribution::Fixed(1.);
+        assert!(Distribution::Mixture(vec![(0., fixed()), (0., fixed())])
+            .load(&dir)
+            .is_err());
+        assert!(Distribution::Mixture(vec![(-1., fixed()), (2., fixed())])
+            .load(&dir)
+            .is_err());
+        assert!(Distribution::Mixture(vec![(0., fixed()), (1., fixed())])
+            .load(&dir)
+            .is_ok());
+        assert!(Distribution::File(PathBuf::from("atp_missing.txt"))
+            .load(&dir)
+            .is_err());
+    }
+
+    #[test]
+    fn shipped_configs_load() {
+        for entry in std::fs::read_dir("configs").unwrap() {
+            let path = entry.unwrap().path();
+            if let Err(e) = Config::load(&path) {
+                panic!("{:?}: {}", path, e);
+            }
+        }
+    }
+
+    #[test]
+    fn sample_u32_truncates_and_clips() {
+        assert_eq!(Distribution::Fixed(2.9).sample_u32(), 2);
+        assert_eq!(Distribution::Fixed(-3.).sample_u32(), 0);
+    }
+
+    #[test]
+    fn migrate_old_keys() {
+        let mut value = serde_json::json!({
+            "greed_mean": 100.,
+            "greed_sd": 10.,
+            "initial_cash": 500,
+            "initial_energy": { "Uniform": { "min": 1., "max": 2. } },
+        });
+        migrate(&mut value);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "greed": { "Normal": { "mean": 100., "sd": 10. } },
+                "initial_cash": { "Fixed": 500 },
+                "initial_energy": { "Uniform": { "min": 1., "max": 2. } },
+            })
+        );
+
+        // A new greed is not replaced by the old keys.
+        let mut value = serde_json::json!({
+            "greed": { "Fixed": 5. },
+            "greed_mean": 100.,
+            "greed_sd": 10.,
+        });
+        migrate(&mut value);
+        assert_eq!(value, serde_json::json!({ "greed": { "Fixed": 5. } }));
+    }
+
+    #[test]
+    fn old_baseline_config_loads() {
+        // The baseline config before the distributions and energy costs.
+        let old = r#"{
+  "ocean_cutoff": -300,
+  "beach_cutoff": -250,
+  "closing_time": 150,
+  "critical_energy": 500,
+  "day_length": 200,
+  "default_exp": 2000,
+  "default_rval": 600,
+  "exploration_timeout": 500,
+  "greed_mean": 5.0,
+  "greed_sd": 10.0,
+  "initial_cash": 20000,
+  "initial_energy": 5000,
+  "initial_inventory": 0,
+  "initial_nutrition": 100,
+  "market_price_update": 0.01,
+  "max_energy": 10000,
+  "energy_cost": 2,
+  "nutrition_add": 4,
+  "nutrition_sub": 9,
+  "order_price_decay": 75,
+  "resource_amount_mean": 20.0,
+  "resource_amount_sd": 10.0,
+  "explorer_fish_points": 50,
+  "explorer_resource_divisor": 10,
+  "resource_timeout": 2000,
+  "search_radius": 15,
+  "timeout_quota": 2000,
+  "unstuckifier_chance": 0.75,
+  "batch_total_step_count": 1000000,
+  "repetitions": 10
+}"#;
+        let dir = std::env::temp_dir();
+        let name = format!("atp_old_baseline_{}.json", std::process::id());
+        std::fs::write(dir.join(&name), old).unwrap();
+        let result = Config::load(&dir.join(&name));
+        std::fs::remove_file(dir.join(&name)).unwrap();
+        let config = result.unwrap();
+        assert_eq!(config.greed, Distribution::Normal { mean: 5., sd: 10. });
+        assert_eq!(config.initial_cash, Distribution::Fixed(20000.));
+        assert_eq!(config.move_energy_cost, 2);
+        assert_eq!(config.rest_energy_cost, 2);
+        assert_eq!(config.repetitions, 10);
+        assert_eq!(config.resources.len(), Config::default().resources.len());
+    }
+}
@@ -1,20 +1,31 @@
 use std::cmp::Ordering;
 
 use rand::{
-    distributions::{Bernoulli, Standard},
+    distributions::{Bernoulli, Standard, WeightedIndex},
     prelude::*,
     Rng,
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    bank::Loan,
     config::Config,
     world::{Pos, World},
 };
-use crate::{market::Market, tile::TileType};
+use crate::{
+    market::{Market, MarketClearing},
+    tile::TileType,
+};
 
 use super::{
+    brain::BrainKind,
     building::Building,
-    resources::{PerResource, ResourceItem},
+    construction::{BuildingKind, Site},
+    crafting::Recipe,
+    learning::{JobLearning, JobScoring, JobValues},
+    memory::Memory,
+    resources::{Good, PerResource, ResourceItem},
+    skill::Skills,
     Entity, EntityId, EntityType,
 };
 
@@ -24,6 +35,8 @@ use super::{
 /// them the files should be read together.
 #[derive(Debug, Clone, Hash)]
 pub struct Agent {
+    /// The brain makes the decisions of this agent, see brain.rs.
+    pub brain: BrainKind,
     /// This contains the agents job, and all variables associated with said
     /// job.
     pub job: Job,
@@ -41,6 +54,15 @@ pub struct Agent {
     pub shopping_list: Option<PerResource<u32>>,
     /// This contains the shopping list for the day
     pub meal_plan: Option<PerResource<u32>>,
+    /// The goods other than food the agent wants to keep or buy on the
+    /// current market visit, like tools and the inputs of recipes.
+    pub needs: Option<PerResource<u32>>,
+    /// How many times the current tool was used.
+    pub tool_wear: u32,
+    /// The position of the market the agent last traded at.
+    pub last_market: Option<Pos>,
+    /// Everything the agent remembers about the world, see memory.rs.
+    pub memory: Memory,
     /// This is the agent's goal for the day in terms of energy. It updates every day.
     /// The main goal is that the agent does not end the day with less energy than they
     /// started. However, if their energy is below 5000 they are going to try and compensate
@@ -48,6 +70,13 @@ pub struct Agent {
     pub energy_quota: u32,
     /// This is the agents current energy. This value is between 0 and 10_000
     pub energy: u32,
+    /// The energy the agent started with, if the energy is below this the
+    /// agent tries to compensate.
+    pub initial_energy: u32,
+    /// At this energy the agent stops doing its job and goes home to eat.
+    pub critical_energy: u32,
+    /// What the agent did in the last step, and the energy it cost.
+    pub activity: (Activity, i32),
     /// This is the greed of the agent. It denotes the desired cash profit for each day
     /// It is initialized randomly from a normal distribution. It is initialized as an
     /// integer to satisfy the Hash trait but in use it is divided by 100
@@ -57,41 +86,122 @@ pub struct Agent {
     pub cash: u32,
     // This is the cash that the agent needs to make
     pub cash_quota: u32,
+    /// Cash earned by selling on the market today, this is reset every day
+    /// when the income tax is collected.
+    pub income: u32,
+    /// Moving average of the daily income, the bank lends against this.
+    pub expected_income: u32,
+    /// Cash deposited at the bank.
+    pub deposit: u32,
+    /// The current loan from the bank, agents can only have one loan at a
+    /// time.
+    pub loan: Option<Loan>,
+    /// True if the agent ever defaulted on a loan, the bank will not lend to
+    /// it anymore.
+    pub defaulted: bool,
     // Used to change jobs if quota is not met.
     pub timeout_quota: u16,
+    /// How many times in a row the agent did not meet its cash quota in time,
+    /// this is reset when it meets the quota.
+    pub quota_failures: u32,
+    /// True if the agent wants to move to a hut close to where it works, only
+    /// used if migration is enabled.
+    pub migrating: bool,
+    /// The learned value of every job, only used if job learning is enabled.
+    pub job_values: JobValues,
+    /// The skill of every job, only used if skills are enabled.
+    pub skills: Skills,
     /// This is true when the agent is in a building. To check which building
     /// the agent is in look up the current position in the world.
     pub in_building: bool,
     /// If this is true the agent is dead.
     pub dead: bool,
+    /// The parent of this agent, None for the initial agents.
+    pub parent: Option<EntityId>,
+    /// The generation of this agent, the initial agents are generation zero.
+    pub generation: u32,
+    /// The age of this agent in ticks.
+    pub age: u32,
+    /// Why the agent died, None while it is alive.
+    pub death_cause: Option<DeathCause>,
 }
 
 impl Agent {
     /// Create a new agent based on the config.
     pub fn new(config: &Config) -> Self {
-        let greed = (thread_rng().sample::<f32, _>(rand_distr::StandardNormal) * config.greed_sd
-            + config.greed_mean)
-            .max(0.) as u32;
+        let greed = config.greed.sample_u32();
+        let initial_energy = config.initial_energy.sample_u32();
+        let initial_cash = config.initial_cash.sample_u32();
+        let brains = WeightedIndex::new(config.brains.iter().map(|b| b.fraction))
+            .expect("Invalid brain fractions");
+        let job = if Job::Crafter.is_available(config) && random::<f32>() < config.crafters {
+            Job::Crafter
+        } else if Job::Cook.is_available(config) && random::<f32>() < config.cooks {
+            Job::Cook
+        } else {
+            Job::random(config)
+        };
         Agent {
-            job: random(),
+            brain: config.brains[brains.sample(&mut thread_rng())].brain,
+            job,
             state: AgentState::DoJob,
             home: Pos::default(),
-            nutrition: PerResource::new(config.initial_nutrition),
-            inventory: PerResource::new(config.initial_inventory),
-            energy: config.initial_energy,
-            energy_quota: config.initial_energy,
-            // TODO draw this from a normal distribution
+            nutrition: PerResource::from_fn(config, |r| r.config(config).nutrition),
+            inventory: PerResource::new(config.initial_inventory, config),
+            energy: initial_energy,
+            energy_quota: initial_energy,
+            initial_energy,
+            critical_energy: config.critical_energy.sample_u32(),
+            activity: (Activity::Idle, 0),
             greed,
             meal_plan: None,
             shopping_list: None,
-            cash: config.initial_cash,
-            cash_quota: config.initial_cash,
+            needs: None,
+            tool_wear: 0,
+            last_market: None,
+            memory: Memory::default(),
+            cash: initial_cash,
+            cash_quota: initial_cash,
+            income: 0,
+            expected_income: 0,
+            deposit: 0,
+            loan: None,
+            defaulted: false,
             in_building: false,
             dead: false,
             timeout_quota: config.timeout_quota,
+            quota_failures: 0,
+            migrating: false,
+            job_values: JobValues::new(config),
+            skills: Skills::new(config),
+            parent: None,
+            generation: 0,
+            // With aging the initial population has a spread of ages, so they
+            // don't all die around the same time.
+            age: if config.aging {
+                thread_rng().gen_range(0..=config.max_age * config.day_length / 2)
+            } else {
+                0
+            },
+            death_cause: None,
         }
     }
 
+    /// Create a child of this agent. The child inherits the brain and the
+    /// greed of the parent, the greed is mutated with Gaussian noise.
+    pub fn child(&self, id: EntityId, config: &Config) -> Agent {
+        let mut child = Agent::new(config);
+        child.brain = self.brain;
+        child.greed = (self.greed as f32
+            + thread_rng().sample::<f32, _>(rand_distr::StandardNormal) * config.greed_mutation_sd)
+            .round()
+            .max(0.) as u32;
+        child.parent = Some(id);
+        child.generation = self.generation + 1;
+        child.age = 0;
+        child
+    }
+
     /// This function is called once per agent per step, it returns an agent
     /// action which will then be executed by the World struct.
     pub fn step(&mut self, pos: Pos, world: &World) -> AgentAction {
@@ -99,57 +209,206 @@ impl Agent {
             return AgentAction::None;
         }
 
+        self.age += 1;
+        if world.config.aging {
+            let days = self.age_in_days(&world.config);
+            if days >= world.config.max_age as f32 {
+                return AgentAction::Die(DeathCause::OldAge);
+            }
+            let hazard = world.config.mortality.eval(days) / world.config.day_length as f32;
+            if random::<f32>() < hazard {
+                return AgentAction::Die(DeathCause::Hazard);
+            }
+        }
+
+        let action = self.act(pos, world);
+
+        // The energy cost depends on what the agent does in this step.
+        let activity = self.activity(&action, world);
+        let mut cost = activity.energy_cost(&world.config) as f32;
+        if world.config.aging && cost > 0. {
+            cost *= world
+                .config
+                .age_energy_cost
+                .eval(self.age_in_days(&world.config));
+        }
+        // Round the energy cost randomly, so the average cost is right.
+        let cost = cost.floor() as i32 + (random::<f32>() < cost - cost.floor()) as i32;
+        self.activity = (activity, cost);
+
         // Change energy
-        self.energy = self.energy.saturating_sub(world.config.energy_cost);
+        self.energy = (self.energy as i32 - cost).clamp(0, world.config.max_energy as i32) as u32;
         if self.energy == 0 {
-            return AgentAction::Die;
+            return AgentAction::Die(DeathCause::Starvation);
         }
+        // The effort of working lowers the reward of the job.
+        if activity.is_effort() && cost > 0 {
+            self.job_values.reward -= cost as f32 * world.config.learning_energy_weight;
+        }
+        action
+    }
 
+    /// What the agent does in a step, given the action it selected.
+    fn activity(&self, action: &AgentAction, world: &World) -> Activity {
+        let at_home = matches!(
+            self.state,
+            AgentState::BeHome | AgentState::Store | AgentState::Fetch
+        );
+        match action {
+            _ if self.in_building && at_home => {
+                if world.time_of_day() > world.config.closing_time {
+                    Activity::Rest
+                } else {
+                    Activity::Home
+                }
+            }
+            AgentAction::Move(_) | AgentAction::Enter(_) | AgentAction::Leave(_) => {
+                if let Job::Fisher { boat: Some(_) } = self.job {
+                    Activity::Sail
+                } else {
+                    Activity::Move
+                }
+            }
+            AgentAction::Farm(_) | AgentAction::Craft(_) | AgentAction::Construct(..) => {
+                Activity::Work
+            }
+            _ => Activity::Idle,
+        }
+    }
+
+    /// Select the action of this step based on the current state.
+    fn act(&mut self, pos: Pos, world: &World) -> AgentAction {
         self.timeout_quota = self.timeout_quota.saturating_sub(1);
 
+        if world.config.skills {
+            self.skills.decay(&self.job, &world.config);
+        }
+
+        if world.config.memory {
+            self.memory
+                .observe(pos, world.household(self.home).map(|h| h.id), world);
+        }
+
+        let brain = self.brain.brain();
+
         // Select an action based on the current state.
         match self.state {
             AgentState::GoHome => match self.path_find(pos, Some(self.home), world) {
                 Ok(h) => {
                     self.state = AgentState::BeHome;
-                    self.update_quotas(&world.config);
+                    brain.update_quotas(self, &world.config);
+                    if world.config.migration {
+                        self.migrating = self.should_migrate(world);
+                    }
                     AgentAction::Enter(h)
                 }
-                Err(a) => {
-                    // This special case makes fishers leave their boat on the beach
-                    if matches!(self.job, Job::Fisher { boat: Some(_) })
-                        && world.tile_type(pos) == TileType::Sand
-                    {
-                        return if let Some(p) =
-                            world.find_tile_around(pos, 9, |p| world.tile_is_walkable(p))
-                        {
-                            AgentAction::LeaveBoat(p)
-                        } else {
-                            AgentAction::None
-                        };
-                    }
-                    a
+                Err(a) => self.leave_boat(pos, world).unwrap_or(a),
+            },
+            AgentState::GoStore => match self.path_find(pos, Some(self.home), world) {
+                Ok(h) => {
+                    self.state = AgentState::Store;
+                    AgentAction::Enter(h)
                 }
+                Err(a) => self.leave_boat(pos, world).unwrap_or(a),
             },
+            AgentState::Store => {
+                if let Some(action) = self.store(world) {
+                    return action;
+                }
+                // Go back to work.
+                if let Some(p) = world.find_tile_around(pos, 9, |p| self.can_walk_on(p, world)) {
+                    self.state = AgentState::DoJob;
+                    AgentAction::Leave(p)
+                } else {
+                    AgentAction::None
+                }
+            }
+            AgentState::Fetch => {
+                if let Some(action) = self.fetch(world) {
+                    return action;
+                }
+                if let Some(p) = world.find_tile_around(pos, 9, |p| self.can_walk_on(p, world)) {
+                    self.state = AgentState::GoToMarket(None);
+                    AgentAction::Leave(p)
+                } else {
+                    AgentAction::None
+                }
+            }
             AgentState::BeHome => {
-                if self.energy < world.config.initial_energy {
-                    // Eat according to mealplan
+                // Store everything carried home.
+                if world.config.storage {
+                    if let Some(action) = self.store(world) {
+                        return action;
+                    }
+                }
+                // Cook the food bought for a meal before eating.
+                if world.config.home_cooking && self.energy < self.energy_quota {
+                    if let Some(i) = world
+                        .config
+                        .recipes
+                        .iter()
+                        .position(|r| r.is_meal() && r.can_craft(&self.inventory))
+                    {
+                        return AgentAction::Craft(i);
+                    }
+                }
+                if let Some(h) = world
+                    .household(self.home)
+                    .filter(|h| h.is_shared() || world.config.storage)
+                {
+                    // In a shared hut, or with storage, the residents eat from
+                    // the storage, the most nutritious food first, until they
+                    // reach their energy quota.
+                    let needed = self.energy_quota.saturating_sub(self.energy);
+                    let food = ResourceItem::iterator(&world.config)
+                        .filter(|r| h.storage[*r] + self.inventory[*r] > 0)
+                        .filter(|r| self.nutrition[*r] > 0)
+                        .max_by_key(|r| self.nutrition[*r]);
+                    if let (true, Some(r)) = (needed > 0, food) {
+                        let unit_energy = self.nutrition[r] as u32;
+                        let quantity = needed
+                            .div_ceil(unit_energy)
+                            .min(h.storage[r] + self.inventory[r]);
+                        return AgentAction::Consume(r, quantity);
+                    }
+                    // Done eating, plan the next meals on the next market visit.
+                    self.meal_plan = None;
+                } else if self.energy < self.initial_energy {
+                    // Eat the meals first, unless they are for sale, then
+                    // according to mealplan
+                    let needed = self.energy_quota.saturating_sub(self.energy);
+                    let meal = ResourceItem::iterator(&world.config).find(|r| {
+                        r.good(&world.config) == Good::Meal
+                            && self.inventory[*r] > 0
+                            && self.nutrition[*r] > 0
+                            && !self.sells_meal(*r, &world.config)
+                    });
+                    if let (true, Some(r)) = (needed > 0, meal) {
+                        let unit_energy = self.nutrition[r] as u32;
+                        let quantity = needed.div_ceil(unit_energy).min(self.inventory[r]);
+                        return AgentAction::Consume(r, quantity);
+                    }
                     if let Some(_meal_plan) = &self.meal_plan {
-                        for r in ResourceItem::iterator() {
-                            if _meal_plan[*r] > 0 && self.inventory[*r] > 0 {
-                                let quantity: u32 = _meal_plan[*r].min(self.inventory[*r]);
-                                return AgentAction::Consume(*r, quantity);
+                        for r in ResourceItem::iterator(&world.config) {
+                            if _meal_plan[r] > 0 && self.inventory[r] > 0 {
+                                let quantity: u32 = _meal_plan[r].min(self.inventory[r]);
+                                return AgentAction::Consume(r, quantity);
                             }
                         }
                         self.meal_plan = None;
                     }
                 }
+                // Rest at home until the next day.
+                if world.config.night_rest && world.time_of_day() > world.config.closing_time {
+                    return AgentAction::None;
+                }
                 if let Some(p) = world.find_tile_around(pos, 9, |p| self.can_walk_on(p, world)) {
                     // Decide what to do next.
-                    if random() {
-                        self.state = AgentState::DoJob;
-                    } else {
-                        self.state = AgentState::GoToMarket(None);
+                    self.state = brain.leave_home(self, world);
+                    // Take goods from the storage to sell on the market.
+                    if world.config.storage && matches!(self.state, AgentState::GoToMarket(_)) {
+                        self.state = AgentState::Fetch;
+                        return AgentAction::None;
                     }
                     AgentAction::Leave(p)
                 } else {
@@ -157,14 +416,65 @@ impl Agent {
                 }
             }
             AgentState::DoJob => {
-                if self.energy < world.config.critical_energy
+                if self.energy < self.critical_energy
                     || world.time_of_day() > world.config.closing_time
                 {
                     self.state = AgentState::GoHome;
+                } else if self.free_capacity(&world.config) == 0 {
+                    // Bring the goods home, or to the market if the storage
+                    // is full.
+                    let space = world
+                        .household(self.home)
+                        .map_or(0, |h| h.free_space(&world.config));
+                    self.state = if space > 0 {
+                        AgentState::GoStore
+                    } else {
+                        AgentState::GoToMarket(None)
+                    };
+                }
+                let action = brain.do_job(self, pos, world);
+                // Move to a hut close to the resource the agent works on.
+                if let (true, AgentAction::Farm(_)) = (self.migrating, action) {
+                    if let Some(hut) = self.new_home(pos, world) {
+                        self.migrating = false;
+                        self.quota_failures = 0;
+                        return AgentAction::Relocate(hut);
+                    }
+                }
+                action
+            }
+            AgentState::Construct(i, site) => {
+                if self.energy < self.critical_energy
+                    || world.time_of_day() > world.config.closing_time
+                {
+                    self.state = AgentState::GoHome;
+                    return AgentAction::None;
+                }
+                // Step off the tile of the site.
+                if pos == site {
+                    return world
+                        .find_tile_around(pos, 9, |p| self.can_walk_on(p, world))
+                        .map_or(AgentAction::None, AgentAction::Move);
+                }
+                match self.path_find(pos, Some(site), world) {
+                    Ok(p) => self.construct(i, p, world),
+                    Err(a) => a,
                 }
-                self.do_job(pos, world)
             }
             AgentState::GoToMarket(mut m) => {
+                if m.is_none() && world.config.local_markets {
+                    m = self.choose_market(pos, world);
+                }
+                if m.is_none() && world.config.memory {
+                    // Go to the closest market we remember if none is in sight.
+                    m = world
+                        .find_entity_around(
+                            pos,
+                            world.config.search_radius * world.config.search_radius,
+                            |e| matches!(e.ty, EntityType::Building(Building::Market)),
+                        )
+                        .or_else(|| self.memory.nearest_market(pos, world));
+                }
                 let action = self.find(world, pos, &mut m, |e| {
                     matches!(e.ty, EntityType::Building(Building::Market))
                 });
@@ -180,14 +490,20 @@ impl Agent {
                 }
             }
             AgentState::TradeOnMarket => {
+                if world.config.local_markets {
+                    self.memory
+                        .remember_prices(pos, world.market_at(pos), world.tick);
+                }
                 if world.time_of_day() < world.config.closing_time {
-                    if let Some(action) = self.trade_on_market(pos, world) {
+                    if let Some(action) = brain.trade_on_market(self, pos, world) {
+                        self.last_market = Some(pos);
                         return action;
                     }
                 }
                 // Leave the market
                 if let Some(p) = world.find_tile_around(pos, 9, |p| self.can_walk_on(p, world)) {
                     self.state = AgentState::GoHome;
+                    self.needs = None;
                     AgentAction::Leave(p)
                 } else {
                     AgentAction::None
@@ -199,12 +515,36 @@ impl Agent {
     /// Select the appropriate agent action to do the job the agent selected.
     pub fn do_job(&mut self, pos: Pos, world: &World) -> AgentAction {
         match &mut self.job {
-            Job::Lumberer => self.find_and_farm(world, pos, ResourceItem::Berry),
-            Job::Farmer => self.find_and_farm(world, pos, ResourceItem::Wheat),
-            Job::Butcher => self.find_and_farm(world, pos, ResourceItem::Meat),
+            Job::Lumberer => self.find_and_farm(world, pos, JobKind::Lumberer),
+            Job::Farmer => self.find_and_farm(world, pos, JobKind::Farmer),
+            Job::Butcher => self.find_and_farm(world, pos, JobKind::Butcher),
+            Job::Crafter | Job::Cook => {
+                let job = self.job.clone();
+                match world
+                    .config
+                    .recipes
+                    .iter()
+                    .position(|r| job.crafts(r) && r.can_craft(&self.inventory))
+                {
+                    Some(recipe) => AgentAction::Craft(recipe),
+                    // Buy the inputs on the market.
+                    None => {
+                        self.state = AgentState::GoToMarket(None);
+                        AgentAction::None
+                    }
+                }
+            }
             Job::Fisher { boat } => {
                 // Do this if the agent is in a boat.
-                if boat.is_some() {
+                if let Some(b) = boat {
+                    // Sail back once the boat is full.
+                    if let EntityType::Building(Building::Boat { catch, .. }) = world.entity(*b).ty
+                    {
+                        if catch >= world.config.boat_capacity {
+                            self.state = AgentState::GoHome;
+                            return AgentAction::None;
+                        }
+                    }
                     // if on sand: go water
                     if let TileType::Sand = world.tile_type(pos) {
                         let target = world.find_tile_around(
@@ -223,22 +563,54 @@ impl Agent {
                             .unwrap_or_else(|a| a)
                     } else {
                         // if on water
-                        self.find_and_farm(world, pos, ResourceItem::Fish)
+                        self.find_and_farm(world, pos, JobKind::Fisher)
                     }
                 }
                 // Look for a boat on a beach
                 else {
                     // First find a boat and enter it
-                    let target_pos = world.find_entity_around(
+                    let household = world.household(self.home).map(|h| h.id);
+                    let is_boat = |e: &Entity| match &e.ty {
+                        EntityType::Building(b) => b.is_usable_boat(household, &world.config),
+                        _ => false,
+                    };
+                    let mut target_pos = world.find_entity_around(
                         pos,
                         world.config.search_radius * world.config.search_radius,
-                        |e| matches!(e.ty, EntityType::Building(Building::Boat { .. })),
+                        is_boat,
                     );
+                    if target_pos.is_none() && world.config.memory {
+                        target_pos = self.memory.nearest_boat(pos, world);
+                    }
+                    // Put an own boat on the beach if there is none around.
+                    if let (None, Some(b)) = (target_pos, ResourceItem::boat(&world.config)) {
+                        if self.inventory[b] > 0 {
+                            let beach = world.find_tile_around(
+                                pos,
+                                world.config.search_radius * world.config.search_radius,
+                                |p| {
+                                    world.tile_type(p) == TileType::Sand
+                                        && world.tile_is_walkable(p)
+                                },
+                            );
+                            return match self.path_find(pos, beach, world) {
+                                Ok(p) => AgentAction::PlaceBoat(p),
+                                Err(a) => a,
+                            };
+                        }
+                    }
 
                     let pf = self.path_find(pos, target_pos, world);
 
                     match pf {
-                        Ok(p) => AgentAction::EnterBoat(p),
+                        // A remembered boat might have been taken by someone else.
+                        Ok(p) if world.entity_at(p).is_some_and(is_boat) => {
+                            AgentAction::EnterBoat(p)
+                        }
+                        Ok(p) => {
+                            self.memory.forget(p);
+                            AgentAction::None
+                        }
                         Err(a) => a,
                     }
                 }
@@ -259,7 +631,11 @@ impl Agent {
                                     r.available() as u32 / world.config.explorer_resource_divisor
                             }
                             EntityType::Building(Building::Boat { .. }) => {
-                                observations.fish += world.config.explorer_fish_points
+                                if let Some(fish) =
+                                    ResourceItem::harvested_by(JobKind::Fisher, &world.config)
+                                {
+                                    observations[fish] += world.config.explorer_fish_points
+                                }
                             }
                             _ => (),
                         }
@@ -271,8 +647,26 @@ impl Agent {
 
                 // Select the highest scoring job.
                 if *count == world.config.exploration_timeout {
+                    // With memory every resource and boat is only counted
+                    // once, instead of every time it is seen.
+                    if world.config.memory {
+                        *observations = PerResource::new(0, &world.config);
+                        for r in &self.memory.resources {
+                            observations[r.item] +=
+                                r.amount as u32 / world.config.explorer_resource_divisor;
+                        }
+                        if let Some(fish) =
+                            ResourceItem::harvested_by(JobKind::Fisher, &world.config)
+                        {
+                            observations[fish] +=
+                                self.memory.boats.len() as u32 * world.config.explorer_fish_points;
+                        }
+                    }
+
+                    // Copied so the agent can be borrowed to choose a market.
+                    let observations = observations.clone();
                     let mut max_freq: u32 = 0;
-                    let mut best_item: ResourceItem = ResourceItem::Berry;
+                    let mut best_item: ResourceItem = ResourceItem::from_index(0);
                     for (resource, observation) in observations.iter() {
                         if *observation > max_freq {
                             max_freq = *observation;
@@ -280,12 +674,58 @@ impl Agent {
                         }
                     }
 
-                    match best_item {
-                        ResourceItem::Berry => self.job = Job::Lumberer,
-                        ResourceItem::Wheat => self.job = Job::Farmer,
-                        ResourceItem::Fish => self.job = Job::Fisher { boat: None },
-                        ResourceItem::Meat => self.job = Job::Butcher,
+                    // The market the agent would trade at, with local markets
+                    // the one it would choose from here.
+                    let market = match world.config.explorer_scoring {
+                        JobScoring::Market if world.config.local_markets => {
+                            self.choose_market(pos, world).or(self.last_market)
+                        }
+                        JobScoring::Market => self.last_market,
+                        _ => None,
                     }
+                    .map(|m| world.market_at(m))
+                    .or_else(|| world.markets.first());
+                    if let (JobScoring::Market, Some(market)) =
+                        (world.config.explorer_scoring, market)
+                    {
+                        // Before anything is traded all incomes are zero, then
+                        // the observations decide.
+                        let demand = |r: ResourceItem| {
+                            (market.market_demand[r] + market.previous_demand[r]) as f32
+                        };
+                        let average_demand = ResourceItem::iterator(&world.config)
+                            .map(demand)
+                            .sum::<f32>()
+                            / world.config.resources.len() as f32;
+                        // The own yields and the observation points have
+                        // different units, so both are scaled to at most one
+                        // before they are mixed.
+                        let yields = &self.job_values.yields;
+                        let max_yield = yields
+                            .iter()
+                            .filter_map(|(_, y)| *y)
+                            .fold(0., f32::max)
+                            .max(f32::EPSILON);
+                        let max_observation = (max_freq as f32).max(1.);
+                        if let Some((r, _)) = observations
+                            .iter()
+                            .map(|(r, o)| {
+                                let amount = yields[r]
+                                    .map_or(*o as f32 / max_observation, |y| y / max_yield);
+                                let demand = (demand(r) + 1.) / (average_demand + 1.);
+                                (r, amount * market.market_price[r] * demand)
+                            })
+                            .filter(|(_, income)| *income > 0.)
+                            .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+                        {
+                            best_item = r;
+                        }
+                    }
+
+                    // Explore again if nothing that can be harvested was seen.
+                    self.job = best_item
+                        .job(&world.config)
+                        .map_or_else(|| Job::explorer(&world.config), JobKind::job);
                 }
 
                 // Walk in a random direction
@@ -301,9 +741,10 @@ impl Agent {
         }
     }
 
-    /// make the mealing plan and return it if possible.
-    pub fn make_mealing_plan(&self, market: &Market) -> Option<PerResource<u32>> {
-        let mut to_ret: PerResource<u32> = PerResource::default();
+    /// make the mealing plan and return it if possible. With home cooking the
+    /// plan can contain the ingredients of a meal.
+    pub fn make_mealing_plan(&self, market: &Market, config: &Config) -> Option<PerResource<u32>> {
+        let mut to_ret: PerResource<u32> = PerResource::new(0, config);
 
         if self.energy >= self.energy_quota {
             return None;
@@ -312,31 +753,68 @@ impl Agent {
         // Calculating the energy needed to fulfill the quota and updating it as the meal plan is constructed
         let mut needed_energy = self.energy_quota.saturating_sub(self.energy);
 
+        // Buy the ingredients of a meal if cooking it gives more energy per
+        // cash than any food on the market.
+        if config.home_cooking {
+            let value = |energy: u32, price: u32| energy as u64 * 1_000_000 / (price as u64 + 1);
+            let best_food = ResourceItem::iterator(config)
+                .filter(|r| r.is_food(config))
+                .map(|r| value(self.nutrition[r] as u32, market.market_price(r)))
+                .max()
+                .unwrap_or(0);
+            let best_meal = config
+                .recipes
+                .iter()
+                .filter(|c| c.is_meal())
+                .map(|c| (c.inputs(), c.amount * self.nutrition[c.output()] as u32))
+                .filter(|(_, energy)| *energy > 0)
+                .max_by_key(|(inputs, energy)| value(*energy, market.total_price(inputs)));
+            if let Some((inputs, energy)) = best_meal {
+                if value(energy, market.total_price(inputs)) > best_food {
+                    // Cook as often as needed, and as the ingredients allow.
+                    let possible = inputs
+                        .iter()
+                        .filter(|(_, a)| **a > 0)
+                        .map(|(r, a)| (market.availability(r) + self.inventory[r]) / a)
+                        .min()
+                        .unwrap_or(0);
+                    let crafts = needed_energy.div_ceil(energy).min(possible);
+                    for (r, a) in inputs.iter() {
+                        to_ret[r] += a * crafts;
+                    }
+                    needed_energy = needed_energy.saturating_sub(crafts * energy);
+                    if needed_energy == 0 {
+                        return Some(to_ret);
+                    }
+                }
+            }
+        }
+
         // Finding the maximum projected energy over projected price (benefit) of each resource type on the market
-        for r_item in ResourceItem::sorted(self, &market).iter() {
+        for r_item in ResourceItem::sorted(self, market) {
             // Calculating the energy gained by a single unit of that item
             // and the needed amount to fulfill the quota
-            let unit_energy = self.nutrition[*r_item] as u32;
+            let unit_energy = self.nutrition[r_item] as u32;
             if unit_energy == 0 {
                 continue;
             }
             let needed_amount: u32 =
                 needed_energy / unit_energy + ((needed_energy % unit_energy != 0) as u32);
 
-            // to_ret[*r_item] = needed_amount;
+            // to_ret[r_item] = needed_amount;
 
             // If the market or the inventory has more than the needed amount,
             // we can buy it and the agent doesn't need anything else in its mealing plan
-            let availability = market.availability(*r_item) + self.inventory[*r_item];
+            let availability = market.availability(r_item) + self.inventory[r_item];
             if availability >= needed_amount {
-                to_ret[*r_item] = needed_amount;
+                to_ret[r_item] += needed_amount;
                 return Some(to_ret);
             }
 
             // If the market does not have enough of the resource available, the agent buys whatever
             // is available and  the loop keeps going on other, less cost-efficient resources
-            to_ret[*r_item] = availability;
-            needed_energy = needed_energy.saturating_sub(needed_amount * unit_energy);
+            to_ret[r_item] += availability;
+            needed_energy = needed_energy.saturating_sub(availability * unit_energy);
         }
         Some(to_ret)
     }
@@ -344,16 +822,19 @@ impl Agent {
     /// Every time an agent gets home (finishes the working day), they set an energy quota
     /// for the next day
     pub fn update_quotas(&mut self, config: &Config) {
+        // Learn from the reward of today.
+        self.job_values.update(&self.job, config);
+
         // If the agent's energy is above the baseline, their goal for the next day is simply not to
         // lose any more energy
-        if self.energy >= config.initial_energy {
+        if self.energy >= self.initial_energy {
             self.energy_quota = self.energy;
             return;
         }
 
         // Otherwise, the agent has to compensate - they need to increase their energy the next day
         // by p%, where p is (5000 - energy) / 100
-        let mut p: f32 = (config.initial_energy - self.energy) as f32;
+        let mut p: f32 = (self.initial_energy - self.energy) as f32;
         p /= 10000.0;
 
         let quota_f32 = (self.energy as f32) * (1.0 + p);
@@ -361,12 +842,14 @@ impl Agent {
 
         if self.cash >= self.cash_quota {
             self.timeout_quota = config.timeout_quota;
+            self.quota_failures = 0;
         }
 
         if self.timeout_quota == 0 {
-            self.job = Job::Explorer {
-                count: 0,
-                observations: Default::default(),
+            self.quota_failures += 1;
+            self.job = match config.job_learning {
+                JobLearning::Explorer => Job::explorer(config),
+                policy => self.job_values.select(policy, config),
             };
             self.timeout_quota = config.timeout_quota;
         }
@@ -376,60 +859,318 @@ impl Agent {
         self.cash_quota = self.cash + ((self.cash as f32) * desired_profit) as u32;
     }
 
-    /// Subtract the inventory from the mealing plan.
-    fn make_shopping_list(&self, meal_plan: &Option<PerResource<u32>>) -> Option<PerResource<u32>> {
+    /// The goods other than food the agent wants to have. Crafters and cooks
+    /// want the inputs of their most profitable recipe at this market,
+    /// harvesters want a tool and fishers without a boat near their home also
+    /// want a boat.
+    fn make_needs(&self, market: &Market, world: &World) -> PerResource<u32> {
+        let mut needs = PerResource::new(0, &world.config);
+        match self.job {
+            Job::Explorer { .. } => {}
+            Job::Crafter | Job::Cook => {
+                // Goods nobody traded yet have price zero, so crafters also
+                // try recipes without profit to get the market going.
+                let best = world
+                    .config
+                    .recipes
+                    .iter()
+                    .filter(|r| self.job.crafts(r) && r.profit(market) >= 0)
+                    .max_by_key(|r| r.profit(market));
+                if let Some(recipe) = best {
+                    needs = recipe.inputs().map(|a| a * world.config.craft_batches);
+                }
+            }
+            _ => {
+                if let Some(tool) = ResourceItem::tool(&world.config) {
+                    needs[tool] = 1;
+                }
+                // Fishers construct a boat instead if there is a blueprint.
+                let constructed = world
+                    .config
+                    .blueprints
+                    .iter()
+                    .any(|b| b.building == BuildingKind::Boat);
+                if let (Job::Fisher { .. }, Some(boat)) =
+                    (&self.job, ResourceItem::boat(&world.config))
+                {
+                    if !constructed && !self.has_boat(world) {
+                        needs[boat] = 1;
+                    }
+                }
+            }
+        }
+        // The materials still missing for the building the household needs,
+        // agents only invest in buildings when they are not hungry.
+        if let Some(i) = self
+            .wanted_building(world)
+            .filter(|_| self.energy >= self.initial_energy)
+        {
+            let blueprint = &world.config.blueprints[i];
+            let missing = match self.site(blueprint.building, world) {
+                Some((_, site)) => site.missing.clone(),
+                None => blueprint.materials().clone(),
+            };
+            needs = needs.combine(&missing, |n, m| n + m);
+        }
+        needs
+    }
+
+    /// True if there is a boat around the hut the agent can use.
+    fn has_boat(&self, world: &World) -> bool {
+        let household = world.household(self.home).map(|h| h.id);
+        world
+            .find_entity_around(
+                self.home,
+                world.config.search_radius * world.config.search_radius,
+                |e| match &e.ty {
+                    EntityType::Building(b) => b.is_usable_boat(household, &world.config),
+                    _ => false,
+                },
+            )
+            .is_some()
+    }
+
+    /// The index of the blueprint of the building the household of the agent
+    /// is missing, if any. A full household needs a hut for its children if
+    /// there is no empty hut around, a household without a market close by
+    /// needs a market, and a fisher without a boat needs a boat.
+    fn wanted_building(&self, world: &World) -> Option<usize> {
+        let config = &world.config;
+        let radius = config.search_radius * config.search_radius;
+        config.blueprints.iter().position(|b| match b.building {
+            BuildingKind::Hut => {
+                config.births
+                    && world
+                        .household(self.home)
+                        .filter(|h| h.residents.len() >= config.household_size as usize)
+                        .is_some()
+                    && world
+                        .find_entity_around(self.home, radius, |e| {
+                            matches!(&e.ty, EntityType::Building(Building::Hut(h)) if h.residents.is_empty())
+                        })
+                        .is_none()
+            }
+            BuildingKind::Market => {
+                config.market_distance > 0
+                    && world
+                        .find_entity_around(
+                            self.home,
+                            config.market_distance * config.market_distance,
+                            |e| matches!(e.ty, EntityType::Building(Building::Market)),
+                        )
+                        .is_none()
+            }
+            BuildingKind::Boat => matches!(self.job, Job::Fisher { .. }) && !self.has_boat(world),
+        })
+    }
+
+    /// The position of the construction site of the given kind around the hut
+    /// the agent can work on. Markets are constructed by everyone together,
+    /// huts and boats only by the household that started them.
+    fn site<'a>(&self, kind: BuildingKind, world: &'a World) -> Option<(Pos, &'a Site)> {
+        let household = world.household(self.home).map(|h| h.id);
+        let is_site = |e: &Entity| {
+            matches!(&e.ty, EntityType::Building(Building::Site(s))
+                if s.kind == kind && (kind == BuildingKind::Market || Some(s.owner) == household))
+        };
+        let pos = world.find_entity_around(
+            self.home,
+            world.config.search_radius * world.config.search_radius,
+            is_site,
+        )?;
+        match world.entity_at(pos).map(|e| &e.ty) {
+            Some(EntityType::Building(Building::Site(s))) => Some((pos, s)),
+            _ => None,
+        }
+    }
+
+    /// Decide whether to construct the building the household needs when
+    /// leaving home. The agent works on the site of the building if it has
+    /// all the materials or brings some of the missing ones, otherwise it
+    /// starts a new site next to its hut if the household has all the
+    /// materials. Returns None if there is nothing to construct.
+    pub fn construction(&self, world: &World) -> Option<AgentState> {
+        let i = self.wanted_building(world)?;
+        let blueprint = &world.config.blueprints[i];
+        // The goods of a household without storage are shared when leaving.
+        let mut goods = self.inventory.clone();
+        if let Some(h) = world.household(self.home).filter(|_| !world.config.storage) {
+            goods = goods.combine(&h.storage, |a, b| a + b);
+        }
+        if let Some((pos, site)) = self.site(blueprint.building, world) {
+            let helps =
+                site.is_supplied() || site.missing.iter().any(|(r, a)| *a > 0 && goods[r] > 0);
+            return Some(AgentState::Construct(site.blueprint, pos)).filter(|_| helps);
+        }
+        if !blueprint.materials().iter().all(|(r, a)| goods[r] >= *a) {
+            return None;
+        }
+        world
+            .find_tile_around(
+                self.home,
+                world.config.search_radius * world.config.search_radius,
+                |p| blueprint.building.suitable(p, world),
+            )
+            .map(|p| AgentState::Construct(i, p))
+    }
+
+    /// True if the agent should move closer to its work. Agents harvesting
+    /// resources migrate when they know no resource of their job within the
+    /// migration distance of their hut, all agents when they failed to meet
+    /// their cash quota too often.
+    fn should_migrate(&self, world: &World) -> bool {
+        let config = &world.config;
+        if config.migration_failures > 0 && self.quota_failures >= config.migration_failures {
+            return true;
+        }
+        if !ResourceItem::iterator(&world.config).any(|r| self.job.harvests(r, &world.config)) {
+            return false;
+        }
+        // Without memory the agents know the resources around their hut.
+        let distance = config.migration_distance;
+        if config.memory {
+            !self.memory.resources.iter().any(|r| {
+                self.job.harvests(r.item, &world.config)
+                    && r.pos.distance(self.home, world) <= distance as u32
+            })
+        } else {
+            world
+                .find_entity_around(self.home, distance * distance, |e| {
+                    matches!(&e.ty, EntityType::Resource(r)
+                        if self.job.harvests(r.product(), &world.config) && r.available() > 0)
+                })
+                .is_none()
+        }
+    }
+
+    /// The hut to move into when migrating, an empty hut close to the given
+    /// position, or a free tile to build a new hut on if huts are not
+    /// constructed by the agents.
+    fn new_home(&self, pos: Pos, world: &World) -> Option<Pos> {
+        let radius = world.config.search_radius * world.config.search_radius;
+        let constructed = world
+            .config
+            .blueprints
+            .iter()
+            .any(|b| b.building == BuildingKind::Hut);
+        world
+            .find_entity_around(pos, radius, |e| {
+                matches!(&e.ty, EntityType::Building(Building::Hut(h)) if h.residents.is_empty())
+            })
+            .or_else(|| {
+                if constructed {
+                    return None;
+                }
+                world.find_tile_around(pos, radius, |p| {
+                    world.tile_type(p) == TileType::Grass && world.tile_is_walkable(p)
+                })
+            })
+    }
+
+    /// Select the action at the construction site at the given position, the
+    /// agent starts the site if the tile is still free. The agent goes back
+    /// to its job if the building is finished, or it can not help.
+    fn construct(&mut self, i: usize, p: Pos, world: &World) -> AgentAction {
+        let kind = world.config.blueprints[i].building;
+        match world.entity_at(p).map(|e| &e.ty) {
+            None if kind.suitable(p, world) => return AgentAction::Construct(i, p),
+            // Wait for the agent on the tile to move away.
+            Some(EntityType::Agent(_)) => return AgentAction::None,
+            Some(EntityType::Building(Building::Site(s)))
+                if s.blueprint == i
+                    && (s.is_supplied()
+                        || s.missing
+                            .iter()
+                            .any(|(r, a)| *a > 0 && self.inventory[r] > 0)) =>
+            {
+                return AgentAction::Construct(i, p)
+            }
+            _ => {}
+        }
+        self.state = AgentState::DoJob;
+        AgentAction::None
+    }
+
+    /// Subtract the inventory from the mealing plan and the needs.
+    fn make_shopping_list(
+        &self,
+        meal_plan: &Option<PerResource<u32>>,
+        needs: &PerResource<u32>,
+    ) -> Option<PerResource<u32>> {
         // It subtracts the stuff they need from the stuff they have, so they
         // don't buy excessively If you need a product, you check how much of it
         // you have and you put the rest on your shopping list
-        let mut to_ret: PerResource<u32> = PerResource::default();
+        let mut to_ret: PerResource<u32> = needs.map(|_| 0);
 
-        if let Some(_meal_plan) = meal_plan {
-            // Boolean flag about whether there is a single item on the shopping list
-            let mut empty: bool = true;
-            for r_item in ResourceItem::iterator() {
-                // The item is only added to the shopping list if the agent
-                // currently has less than it needs
-                if _meal_plan[*r_item] > self.inventory[*r_item] {
-                    to_ret[*r_item] = _meal_plan[*r_item].saturating_sub(self.inventory[*r_item]);
-                    empty = false;
-                }
+        if meal_plan.is_none() && needs.iter().all(|(_, a)| *a == 0) {
+            return None;
+        }
+        let wanted = match meal_plan {
+            Some(_meal_plan) => _meal_plan.combine(needs, |m, n| m + n),
+            None => needs.clone(),
+        };
+
+        // Boolean flag about whether there is a single item on the shopping list
+        let mut empty: bool = true;
+        for r_item in needs.keys() {
+            // The item is only added to the shopping list if the agent
+            // currently has less than it needs
+            if wanted[r_item] > self.inventory[r_item] {
+                to_ret[r_item] = wanted[r_item].saturating_sub(self.inventory[r_item]);
+                empty = false;
             }
+        }
 
-            if empty {
-                return None;
-            }
-            Some(to_ret)
-        } else {
-            None
+        if empty {
+            return None;
         }
+        Some(to_ret)
     }
 
     /// Select the appropriate action for trading on the market, this is only
     /// valid if the agent is in a market.
-    pub fn trade_on_market(&mut self, _pos: Pos, world: &World) -> Option<AgentAction> {
+    pub fn trade_on_market(&mut self, pos: Pos, world: &World) -> Option<AgentAction> {
         // If a shopping list is not constructed and the energy is below the
         // quota, it constructs it
-        let market: &Market = &world.market;
+        let market: &Market = world.market_at(pos);
 
         if self.meal_plan.is_none() {
-            self.meal_plan = self.make_mealing_plan(market);
+            self.meal_plan = self.make_mealing_plan(market, &world.config);
+        }
+
+        if self.needs.is_none() {
+            self.needs = Some(self.make_needs(market, world));
         }
 
         if self.shopping_list.is_none() {
-            self.shopping_list = self.make_shopping_list(&self.meal_plan);
+            let needs = self
+                .needs
+                .clone()
+                .unwrap_or_else(|| PerResource::new(0, &world.config));
+            self.shopping_list = self.make_shopping_list(&self.meal_plan, &needs);
+        }
+
+        if world.config.bank {
+            if let Some(action) = self.use_bank(market, &world.config) {
+                return Some(action);
+            }
         }
 
         // After a shopping list has been constructed, it sells everything they
         // don't need
 
-        for r_item in ResourceItem::iterator() {
+        for r_item in ResourceItem::iterator(&world.config) {
+            let needed = self.needs.as_ref().map_or(0, |n| n[r_item]);
             let excess: u32 = match &self.meal_plan {
                 Some(_meal_plan) => {
-                    if self.inventory[*r_item] > _meal_plan[*r_item] {
-                        self.inventory[*r_item] - _meal_plan[*r_item]
-                    } else {
-                        0
-                    }
+                    self.inventory[r_item].saturating_sub(_meal_plan[r_item] + needed)
+                }
+                // Without a meal plan only the goods that can not be eaten
+                // are sold, and the meals made by cooks.
+                None if !r_item.is_food(&world.config)
+                    || self.sells_meal(r_item, &world.config) =>
+                {
+                    self.inventory[r_item].saturating_sub(needed)
                 }
                 None => 0,
             };
@@ -453,12 +1194,15 @@ impl Agent {
             // insufficiency = 30   balance = 10 price = 3
             let insufficiency = self.cash_quota.saturating_sub(balance_after_purchase);
             if insufficiency > 0 {
+                // The seller pays the sales tax, so it has to be added to the price.
+                let insufficiency =
+                    (insufficiency as f32 / (1. - world.config.sales_tax)).ceil() as u32;
                 let price = insufficiency / excess + (insufficiency % excess != 0) as u32;
                 let price = price.max(0);
 
                 // Finally it puts the order on the action list
                 return Some(AgentAction::MarketOrder {
-                    item: *r_item,
+                    item: r_item,
                     price,
                     amount: excess,
                 });
@@ -474,14 +1218,45 @@ impl Agent {
         let mut purchased_item: Option<ResourceItem> = None;
 
         if let Some(s_list) = &self.shopping_list {
-            for r_item in ResourceItem::iterator() {
-                if s_list[*r_item] == 0 {
+            for r_item in ResourceItem::iterator(&world.config) {
+                if s_list[r_item] == 0 {
                     continue;
                 }
-                purchased_item = Some(*r_item);
+                purchased_item = Some(r_item);
                 action = AgentAction::MarketPurchase {
-                    item: *r_item,
-                    amount: s_list[*r_item],
+                    item: r_item,
+                    amount: s_list[r_item].min(self.free_capacity(&world.config)),
+                };
+            }
+        }
+
+        // In a call auction the purchase becomes a bid, the cash is split evenly
+        // over everything left on the shopping list. The agent does not bid
+        // more than it expects to need, a markup over the market price. Until
+        // the first trade there is no market price, so the cheapest order is
+        // used instead.
+        if world.config.market_clearing == MarketClearing::CallAuction {
+            if let (AgentAction::MarketPurchase { item, amount }, Some(s_list)) =
+                (action, &self.shopping_list)
+            {
+                let units = s_list.iter().map(|(_, a)| *a).sum::<u32>();
+                let reference = match market.market_price(item) {
+                    0 => market.cheapest_prices()[item].unwrap_or(0),
+                    p => p,
+                };
+                let needed = (reference.max(1) as f32 * world.config.bid_markup).ceil();
+                let price = match units {
+                    0 => 0,
+                    _ => (self.cash / units).min(needed as u32),
+                };
+                action = if price > 0 && amount > 0 {
+                    AgentAction::MarketBid {
+                        item,
+                        price,
+                        amount,
+                    }
+                } else {
+                    AgentAction::None
                 };
             }
         }
@@ -503,6 +1278,109 @@ impl Agent {
         }
     }
 
+    /// Decide whether to go to the bank. If the agent can not afford its
+    /// shopping list it first withdraws its deposit and then borrows the rest.
+    /// Greedy agents borrow more than they need. If the agent has more cash
+    /// than its quota and its shopping list require, it deposits the rest.
+    fn use_bank(&self, market: &Market, config: &Config) -> Option<AgentAction> {
+        // Estimate the cost of the shopping list from the cheapest orders.
+        let cheapest = market.cheapest_prices();
+        let cost = match &self.shopping_list {
+            Some(list) => list
+                .iter()
+                .map(|(r, a)| a * cheapest[r].unwrap_or_else(|| market.market_price(r)))
+                .sum(),
+            None => 0,
+        };
+
+        if cost > self.cash {
+            let shortfall = cost - self.cash;
+            if self.deposit > 0 {
+                return Some(AgentAction::BankWithdraw(shortfall.min(self.deposit)));
+            }
+            if self.loan.is_none() && !self.defaulted {
+                let amount = shortfall as f32 * (1. + self.greed as f32 / 100.);
+                let amount = (amount as u32).min(self.credit_limit(market, config));
+                if amount > 0 {
+                    return Some(AgentAction::Borrow(amount));
+                }
+            }
+        } else if self.loan.is_none() {
+            let surplus = self.cash.saturating_sub(self.cash_quota + cost);
+            if surplus > 0 {
+                return Some(AgentAction::BankDeposit(surplus));
+            }
+        }
+        None
+    }
+
+    /// The maximum amount the bank will lend to this agent, such that the
+    /// installments are payable from the expected income. The inventory is
+    /// also counted as income, since it can be sold on the market.
+    pub fn credit_limit(&self, market: &Market, config: &Config) -> u32 {
+        let income = self.expected_income + market.total_price(&self.inventory) / config.loan_term;
+        let installment = income as f32 * config.loan_income_share;
+        (installment * config.loan_term as f32
+            / (1. + config.loan_interest * config.loan_term as f32)) as u32
+    }
+
+    /// Select the market to go to among all the markets in the search radius,
+    /// and the remembered markets if memory is enabled.
+    /// Every market is scored by the distance to it plus its remembered basket
+    /// price, minus the remembered amount of goods offered on it and a bonus
+    /// for the market the agent last traded at. Markets the agent does not
+    /// know are assumed to have an average price and no goods offered.
+    pub fn choose_market(&self, pos: Pos, world: &World) -> Option<Pos> {
+        let mut candidates = Vec::new();
+        world.find_entity_around(
+            pos,
+            world.config.search_radius * world.config.search_radius,
+            |e| {
+                if let EntityType::Building(Building::Market) = e.ty {
+                    candidates.push(e.pos);
+                }
+                false
+            },
+        );
+        if world.config.memory {
+            for m in &self.memory.markets {
+                if !candidates.contains(&m.pos) {
+                    candidates.push(m.pos);
+                }
+            }
+        }
+
+        let basket = |prices: &PerResource<u32>| prices.iter().map(|(_, p)| *p).sum::<u32>();
+        let known = self
+            .memory
+            .markets
+            .iter()
+            .filter_map(|m| m.prices.as_ref())
+            .map(basket)
+            .collect::<Vec<_>>();
+        let average_basket = if known.is_empty() {
+            0
+        } else {
+            known.iter().sum::<u32>() / known.len() as u32
+        };
+
+        let score = |m: &Pos| {
+            let price = self.memory.prices(*m).map(basket).unwrap_or(average_basket);
+            let depth = self.memory.depth(*m).map_or(0, basket);
+            let loyalty = if self.last_market == Some(*m) {
+                world.config.market_loyalty
+            } else {
+                0.
+            };
+            m.distance(pos, world) as f32 + price as f32 * world.config.market_price_weight
+                - depth as f32 * world.config.market_depth_weight
+                - loyalty
+        };
+        candidates
+            .into_iter()
+            .min_by(|a, b| score(a).partial_cmp(&score(b)).unwrap_or(Ordering::Equal))
+    }
+
     /// Find an entity in the world, caching its position. This will return
     /// Err(action) with an appropriate action to reach the target, or
     /// Ok(target), when it is reached
@@ -526,23 +1404,35 @@ impl Agent {
 
     /// This function will return actions that lead to the agents locating a
     /// resource and farming it.
-    pub fn find_and_farm(&mut self, world: &World, pos: Pos, item: ResourceItem) -> AgentAction {
-        let target_pos = world.find_entity_around(
+    pub fn find_and_farm(&mut self, world: &World, pos: Pos, job: JobKind) -> AgentAction {
+        let is_resource = |e: &Entity| {
+            if let EntityType::Resource(r) = &e.ty {
+                r.product().job(&world.config) == Some(job) && r.available() > 0
+            } else {
+                false
+            }
+        };
+        let mut target_pos = world.find_entity_around(
             pos,
             world.config.search_radius * world.config.search_radius,
-            |e| {
-                if let EntityType::Resource(r) = &e.ty {
-                    r.produces_item(item) && r.available() > 0
-                } else {
-                    false
-                }
-            },
+            is_resource,
         );
+        // If nothing is in sight, walk to the closest resource we remember.
+        if target_pos.is_none() && world.config.memory {
+            target_pos = self.memory.nearest_resource(pos, job, world);
+        }
 
         let pf = self.path_find(pos, target_pos, world);
 
         match pf {
-            Ok(target) => AgentAction::Farm(target),
+            Ok(target) if world.entity_at(target).is_some_and(is_resource) => {
+                AgentAction::Farm(target)
+            }
+            // The remembered resource is gone.
+            Ok(target) => {
+                self.memory.forget(target);
+                AgentAction::None
+            }
             Err(a) => a,
         }
     }
@@ -599,28 +1489,257 @@ impl Agent {
         }
     }
 
+    /// Fishers leave their boat when they reach the beach on their way home.
+    fn leave_boat(&self, pos: Pos, world: &World) -> Option<AgentAction> {
+        if matches!(self.job, Job::Fisher { boat: Some(_) })
+            && world.tile_type(pos) == TileType::Sand
+        {
+            return Some(
+                if let Some(p) = world.find_tile_around(pos, 9, |p| world.tile_is_walkable(p)) {
+                    AgentAction::LeaveBoat(p)
+                } else {
+                    AgentAction::None
+                },
+            );
+        }
+        None
+    }
+
+    /// The total amount of goods the agent carries.
+    pub fn carried(&self) -> u32 {
+        self.inventory.iter().map(|(_, a)| *a).sum()
+    }
+
+    /// The amount of goods the agent can still carry, this is only limited if
+    /// storage is enabled.
+    pub fn free_capacity(&self, config: &Config) -> u32 {
+        if !config.storage {
+            return u32::MAX;
+        }
+        config.carry_capacity.saturating_sub(self.carried())
+    }
+
+    /// True if the agent makes the given meal to sell it.
+    fn sells_meal(&self, r: ResourceItem, config: &Config) -> bool {
+        matches!(self.job, Job::Cook) && r.good(config) == Good::Meal
+    }
+
+    /// The amount of the given good the agent keeps on it for its job, instead
+    /// of storing it at home. Crafters and cooks keep the inputs of their
+    /// recipes, harvesters a tool and fishers their boats.
+    fn kept(&self, r: ResourceItem, config: &Config) -> u32 {
+        match (&self.job, r.good(config)) {
+            (Job::Crafter | Job::Cook, _)
+                if config
+                    .recipes
+                    .iter()
+                    .any(|c| self.job.crafts(c) && c.inputs()[r] > 0) =>
+            {
+                u32::MAX
+            }
+            (Job::Explorer { .. } | Job::Crafter | Job::Cook, _) => 0,
+            (_, Good::Tool { .. }) => 1,
+            (Job::Fisher { .. }, Good::Boat) => u32::MAX,
+            _ => 0,
+        }
+    }
+
+    /// Store the carried goods in the hut, one kind of good per step. Returns
+    /// None if nothing more can be stored.
+    fn store(&self, world: &World) -> Option<AgentAction> {
+        let space = world.household(self.home)?.free_space(&world.config);
+        // The materials for the building the household needs are kept too.
+        let materials = self
+            .wanted_building(world)
+            .map(|i| world.config.blueprints[i].materials().clone())
+            .unwrap_or_else(|| PerResource::new(0, &world.config));
+        let (r, amount) = self
+            .inventory
+            .iter()
+            .map(|(r, a)| {
+                let kept = self.kept(r, &world.config).saturating_add(materials[r]);
+                (r, a.saturating_sub(kept))
+            })
+            .find(|(_, a)| *a > 0)?;
+        if space == 0 {
+            return None;
+        }
+        Some(AgentAction::Store(r, amount.min(space)))
+    }
+
+    /// Take goods from the hut storage to sell on the market, the largest
+    /// stocks first, until the carry capacity is reached. Returns None if
+    /// nothing more can be taken.
+    fn fetch(&self, world: &World) -> Option<AgentAction> {
+        let free = self.free_capacity(&world.config);
+        let (r, amount) = world
+            .household(self.home)?
+            .storage
+            .iter()
+            .filter(|(_, a)| **a > 0)
+            .max_by_key(|(_, a)| **a)?;
+        if free == 0 {
+            return None;
+        }
+        Some(AgentAction::Retrieve(r, (*amount).min(free)))
+    }
+
+    /// The age of this agent in days.
+    pub fn age_in_days(&self, config: &Config) -> f32 {
+        self.age as f32 / config.day_length as f32
+    }
+
+    /// The chance that farming succeeds, this depends on the age if aging is
+    /// enabled and on the skill if skills are enabled.
+    pub fn productivity(&self, config: &Config) -> f32 {
+        let mut productivity = 1.;
+        if config.aging {
+            productivity *= config.age_yield.eval(self.age_in_days(config)).min(1.);
+        }
+        if config.skills {
+            productivity *= self.skills.success_chance(&self.job, config);
+        }
+        productivity
+    }
+
+    /// Add cash earned by selling to this agent.
+    pub fn earn(&mut self, amount: u32) {
+        self.cash += amount;
+        self.income += amount;
+        self.job_values.reward += amount as f32;
+    }
+
+    /// Use the tool in the inventory to farm, if the agent has one. Returns
+    /// true if the tool gives an extra unit. The tool breaks after it has
+    /// been used the configured amount of times.
+    pub fn use_tool(&mut self, config: &Config) -> bool {
+        let tool = match ResourceItem::tool(config) {
+            Some(t) if self.inventory[t] > 0 => t,
+            _ => return false,
+        };
+        if let Good::Tool { uses, bonus } = tool.good(config) {
+            self.tool_wear += 1;
+            if self.tool_wear >= uses {
+                self.inventory[tool] -= 1;
+                self.tool_wear = 0;
+            }
+            random::<f32>() < bonus
+        } else {
+            false
+        }
+    }
+
     /// This function will add the given resource to the agents inventory.
     pub fn collect(&mut self, resource: ResourceItem, amount: u32) {
         self.inventory[resource] += amount;
     }
 
+    /// Add a harvested resource to the inventory, and remember the amount for
+    /// the yields of the job.
+    pub fn harvest(&mut self, resource: ResourceItem, amount: u32) {
+        self.collect(resource, amount);
+        self.job_values.harvested[resource] += amount;
+    }
+
     /// REmoves the given resource from th inventory and consumes it. This will
     /// change the nutritional values and the energy.
     pub fn consume(&mut self, resource: ResourceItem, quantity: u32, config: &Config) {
         assert!(self.inventory[resource] > 0);
         self.inventory[resource] = self.inventory[resource].saturating_sub(quantity);
+        let energy_before = self.energy;
         self.energy += (self.nutrition[resource] as u32) * quantity;
         if self.energy > config.max_energy {
             self.energy = config.max_energy;
         }
+        self.job_values.reward +=
+            (self.energy - energy_before) as f32 * config.learning_energy_weight;
 
         for (r, n) in self.nutrition.iter_mut() {
+            if !r.is_food(config) {
+                continue;
+            }
             if r == resource {
                 *n = n.saturating_sub(config.nutrition_sub.saturating_mul(quantity.min(255) as u8));
             } else {
                 *n = n.saturating_add(config.nutrition_add.saturating_mul(quantity.min(255) as u8));
             }
         }
+
+        // A meal makes the agent no longer tired of its ingredients.
+        if resource.good(config) == Good::Meal {
+            for recipe in config.recipes.iter().filter(|c| c.output() == resource) {
+                for (r, amount) in recipe.inputs().iter() {
+                    if *amount > 0 {
+                        let initial = config.resources[r.index()].nutrition;
+                        self.nutrition[r] = self.nutrition[r].max(initial);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// What an agent does in a step, every activity has its own energy cost.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Activity {
+    /// Walking, including entering and leaving buildings.
+    Move,
+    /// Farming, fishing or crafting.
+    Work,
+    /// Moving in a boat.
+    Sail,
+    /// Waiting, for example on the market or when stuck.
+    Idle,
+    /// Being at home during the day.
+    Home,
+    /// Being at home after the closing time.
+    Rest,
+}
+
+impl Activity {
+    /// All activities in the order used by the statistics.
+    pub const ALL: [Activity; 6] = [
+        Activity::Move,
+        Activity::Work,
+        Activity::Sail,
+        Activity::Idle,
+        Activity::Home,
+        Activity::Rest,
+    ];
+
+    /// Name of the activity as used in the statistics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Activity::Move => "move",
+            Activity::Work => "work",
+            Activity::Sail => "sail",
+            Activity::Idle => "idle",
+            Activity::Home => "home",
+            Activity::Rest => "rest",
+        }
+    }
+
+    /// The energy cost per step of this activity.
+    pub fn energy_cost(&self, config: &Config) -> i32 {
+        match self {
+            Activity::Move => config.move_energy_cost,
+            Activity::Work => config.work_energy_cost,
+            Activity::Sail => config.sail_energy_cost,
+            Activity::Idle => config.idle_energy_cost,
+            Activity::Home => config.home_energy_cost,
+            Activity::Rest => config.rest_energy_cost,
+        }
+    }
+
+    /// True if the activity is part of the working day, its energy cost is
+    /// subtracted from the reward of the job learning.
+    pub fn is_effort(&self) -> bool {
+        !matches!(self, Activity::Home | Activity::Rest)
+    }
+
+    /// Index of the activity in Activity::ALL.
+    pub fn index(&self) -> usize {
+        *self as usize
     }
 }
 
@@ -632,6 +1751,15 @@ pub enum AgentState {
     DoJob,
     GoToMarket(Option<Pos>),
     TradeOnMarket,
+    /// Walk home during the day to store the carried goods.
+    GoStore,
+    /// Store the carried goods in the hut, and go back to work.
+    Store,
+    /// Take goods from the hut storage, and go to the market.
+    Fetch,
+    /// Go to the construction site at the given position, and construct the
+    /// building of the blueprint with the given index.
+    Construct(usize, Pos),
 }
 
 #[derive(Debug, Clone, Copy, Hash)]
@@ -654,6 +1782,23 @@ pub enum AgentAction {
     LeaveBoat(Pos),
     /// Consume a resource.
     Consume(ResourceItem, u32),
+    /// Craft once using the recipe with the given index in the config.
+    Craft(usize),
+    /// Put a boat from the inventory on the beach at the given position.
+    PlaceBoat(Pos),
+    /// Move out of the hut, and into the empty hut at the given position. A
+    /// new hut is built if the tile is free.
+    Relocate(Pos),
+    /// Bring materials to the construction site at the given position, or
+    /// work on it if all materials are there. Starts a site for the blueprint
+    /// with the given index if the tile is free.
+    Construct(usize, Pos),
+    /// This is only valid if an agent is in its hut. Move goods from the
+    /// inventory to the storage of the hut.
+    Store(ResourceItem, u32),
+    /// This is only valid if an agent is in its hut. Move goods from the
+    /// storage of the hut to the inventory.
+    Retrieve(ResourceItem, u32),
     /// This is only valid if an agent is in a market. This action will create
     /// an order at the given price with the specified amount
     MarketOrder {
@@ -667,9 +1812,36 @@ pub enum AgentAction {
         item: ResourceItem,
         amount: u32,
     },
+    /// This is only valid if an agent is in a market. This action will place a
+    /// bid for the next call auction, paying price times amount up front.
+    MarketBid {
+        item: ResourceItem,
+        price: u32,
+        amount: u32,
+    },
+    /// This is only valid if an agent is in a market. Deposit cash at the
+    /// bank.
+    BankDeposit(u32),
+    /// This is only valid if an agent is in a market. Withdraw cash from the
+    /// bank.
+    BankWithdraw(u32),
+    /// This is only valid if an agent is in a market. Take a loan from the
+    /// bank.
+    Borrow(u32),
     /// Die: remove this agent from this agent from the world and set its dead
     /// flag to true.
-    Die,
+    Die(DeathCause),
+}
+
+/// The reason an agent died.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DeathCause {
+    /// The agent ran out of energy.
+    Starvation,
+    /// The agent reached the maximum age.
+    OldAge,
+    /// The agent died of the age dependent mortality hazard.
+    Hazard,
 }
 
 /// Current job of the agent.
@@ -685,9 +1857,96 @@ pub enum Job {
         boat: Option<EntityId>,
     },
     Butcher,
+    /// Crafters make goods using the recipes in the config.
+    Crafter,
+    /// Cooks make meals using the meal recipes in the config.
+    Cook,
 }
 
 impl Job {
+    /// An explorer that did not see anything yet.
+    pub fn explorer(config: &Config) -> Job {
+        Job::Explorer {
+            observations: PerResource::new(0, config),
+            count: 0,
+        }
+    }
+
+    /// A random job among the explorer and the harvesting jobs.
+    pub fn random(config: &Config) -> Job {
+        match thread_rng().gen_range(0..=4) {
+            0 => Job::explorer(config),
+            1 => Job::Farmer,
+            2 => Job::Butcher,
+            3 => Job::Lumberer,
+            4 => Job::Fisher { boat: None },
+            _ => unreachable!(),
+        }
+    }
+
+    /// All the jobs an agent can choose to do, this excludes exploring.
+    pub fn choices() -> Vec<Job> {
+        vec![
+            Job::Farmer,
+            Job::Lumberer,
+            Job::Fisher { boat: None },
+            Job::Butcher,
+            Job::Crafter,
+            Job::Cook,
+        ]
+    }
+
+    /// True if this job can be chosen with the given config, crafting and
+    /// cooking are only possible if there are recipes for them.
+    pub fn is_available(&self, config: &Config) -> bool {
+        match self {
+            Job::Crafter | Job::Cook => config.recipes.iter().any(|r| self.crafts(r)),
+            _ => true,
+        }
+    }
+
+    /// True if agents with this job harvest the given resource.
+    pub fn harvests(&self, r: ResourceItem, config: &Config) -> bool {
+        self.choice_index().is_some()
+            && r.job(config).map(|j| j.job().choice_index()) == Some(self.choice_index())
+    }
+
+    /// True if agents with this job use the given recipe, cooks make the meals
+    /// and crafters everything else.
+    pub fn crafts(&self, recipe: &Recipe) -> bool {
+        match self {
+            Job::Crafter => !recipe.is_meal(),
+            Job::Cook => recipe.is_meal(),
+            _ => false,
+        }
+    }
+
+    /// Name of the job, used in the exported statistics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Job::Explorer { .. } => "explorer",
+            Job::Farmer => "farmer",
+            Job::Lumberer => "lumberer",
+            Job::Fisher { .. } => "fisher",
+            Job::Butcher => "butcher",
+            Job::Crafter => "crafter",
+            Job::Cook => "cook",
+        }
+    }
+
+    /// The index of this job in Job::choices, or None for explorers.
+    pub fn choice_index(&self) -> Option<usize> {
+        match self {
+            Job::Explorer { .. } => None,
+            Job::Farmer => Some(0),
+            Job::Lumberer => Some(1),
+            Job::Fisher { .. } => Some(2),
+            Job::Butcher => Some(3),
+            Job::Crafter => Some(4),
+            Job::Cook => Some(5),
+        }
+    }
+
     /// Texture of the agent based on th job.
     pub fn texture(&self) -> i32 {
         match self {
@@ -697,22 +1956,29 @@ impl Job {
             Job::Fisher { boat: Some(_) } => 51,
             Job::Butcher => 13,
             Job::Lumberer => 15,
+            Job::Crafter | Job::Cook => 14,
         }
     }
 }
 
-impl Distribution<Job> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Job {
-        match rng.gen_range(0..=4) {
-            0 => Job::Explorer {
-                observations: Default::default(),
-                count: 0,
-            },
-            1 => Job::Farmer,
-            2 => Job::Butcher,
-            3 => Job::Lumberer,
-            4 => Job::Fisher { boat: None },
-            _ => unreachable!(),
+/// The jobs that harvest resources, without their state. This is used in the
+/// config to tell which job harvests a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum JobKind {
+    Farmer,
+    Lumberer,
+    Fisher,
+    Butcher,
+}
+
+impl JobKind {
+    /// A new job of this kind.
+    pub fn job(self) -> Job {
+        match self {
+            JobKind::Farmer => Job::Farmer,
+            JobKind::Lumberer => Job::Lumberer,
+            JobKind::Fisher => Job::Fisher { boat: None },
+            JobKind::Butcher => Job::Butcher,
         }
     }
 }
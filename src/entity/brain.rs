@@ -0,0 +1,105 @@
+//! The brain of an agent makes the decisions, while the state machine in
+//! Agent::step only handles the mechanics of walking around. Different brains
+//! can be assigned to the agents in the config, to compare heuristics.
+
+use std::fmt::Debug;
+
+use rand::random;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    world::{Pos, World},
+};
+
+use super::agent::{Agent, AgentAction, AgentState};
+
+/// Makes the decisions of an agent. Every method has a default implementation
+/// which uses the heuristics in agent.rs, brains only override the decisions
+/// they want to change.
+pub trait AgentBrain: Debug {
+    /// Called when the agent leaves its hut, returns the state the agent will
+    /// continue in. Agents construct the building their household needs
+    /// first, see Agent::construction.
+    fn leave_home(&self, agent: &mut Agent, world: &World) -> AgentState {
+        if let Some(state) = agent.construction(world) {
+            return state;
+        }
+        if random() {
+            AgentState::DoJob
+        } else {
+            AgentState::GoToMarket(None)
+        }
+    }
+
+    /// Select the action to do the job of the agent, see Agent::do_job.
+    fn do_job(&self, agent: &mut Agent, pos: Pos, world: &World) -> AgentAction {
+        agent.do_job(pos, world)
+    }
+
+    /// Select the action on the market, None makes the agent leave the market,
+    /// see Agent::trade_on_market.
+    fn trade_on_market(&self, agent: &mut Agent, pos: Pos, world: &World) -> Option<AgentAction> {
+        agent.trade_on_market(pos, world)
+    }
+
+    /// Called when the agent gets home, see Agent::update_quotas.
+    fn update_quotas(&self, agent: &mut Agent, config: &Config) {
+        agent.update_quotas(config)
+    }
+}
+
+/// The brains that can be selected in the config.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum BrainKind {
+    /// The greed driven heuristics in agent.rs.
+    Default,
+    /// Like the default brain, but sells at the market price instead of a price
+    /// based on the cash quota.
+    PriceTaker,
+}
+
+impl BrainKind {
+    /// Get the brain of this kind.
+    pub fn brain(self) -> &'static dyn AgentBrain {
+        match self {
+            BrainKind::Default => &DefaultBrain,
+            BrainKind::PriceTaker => &PriceTakerBrain,
+        }
+    }
+}
+
+/// Assigns a brain to a fraction of the agents.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct BrainShare {
+    pub brain: BrainKind,
+    /// Fractions of all shares are normalized, so they do not have to add up
+    /// to one.
+    pub fraction: f32,
+}
+
+/// See [`BrainKind::Default`].
+#[derive(Debug)]
+pub struct DefaultBrain;
+
+impl AgentBrain for DefaultBrain {}
+
+/// See [`BrainKind::PriceTaker`].
+#[derive(Debug)]
+pub struct PriceTakerBrain;
+
+impl AgentBrain for PriceTakerBrain {
+    fn trade_on_market(&self, agent: &mut Agent, pos: Pos, world: &World) -> Option<AgentAction> {
+        let market = world.market_at(pos);
+        agent
+            .trade_on_market(pos, world)
+            .map(|action| match action {
+                AgentAction::MarketOrder { item, amount, .. } => AgentAction::MarketOrder {
+                    item,
+                    price: market.market_price(item).max(1),
+                    amount,
+                },
+                a => a,
+            })
+    }
+}
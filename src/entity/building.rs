@@ -1,74 +1,122 @@
+use rand::{seq::SliceRandom, thread_rng, Rng};
+
 use crate::{config::Config, world::Pos};
 
-use super::{agent::Agent, Entity, EntityId, EntityType};
+use super::{agent::Agent, construction::Site, household::Household, Entity, EntityId, EntityType};
 
-/// There are three types of buildings in the world:
+/// There are four types of buildings in the world:
 /// - Markets: Here agents go to trade.
-/// - Hut: every agent has exactly one hut they call home.
-/// - Boat: These are used by fishers to go fishing.
+/// - Hut: every agent has exactly one hut they call home, multiple agents
+///   living in the same hut form a household.
+/// - Boat: These are used by fishers to go fishing. Boats put on the beach by
+///   an agent belong to its household, the other boats can be used by every
+///   fisher. Boats on the beach are never sold, only boats in an inventory
+///   are traded.
+/// - Site: A building under construction, see construction.rs.
 #[derive(Debug, Clone, Hash)]
 pub enum Building {
     Market,
-    Hut { is_agent_in: bool, agent: EntityId },
-    Boat { has_agent: bool },
+    Hut(Household),
+    Boat {
+        has_agent: bool,
+        /// The id of the household owning the boat, None if it belongs to
+        /// nobody.
+        owner: Option<u32>,
+        /// How many trips the boat made.
+        trips: u32,
+        /// The amount of resources caught on the current trip.
+        catch: u32,
+    },
+    Site(Site),
 }
 
 impl Building {
-    /// Create an uninitialized hut (without an agent). Huts should be
-    /// initialized, by calling initialize later.
-    pub fn hut_uninitialized() -> Building {
-        Building::Hut {
-            is_agent_in: true,
-            agent: EntityId::uninitialized(),
+    /// Create a boat on the beach, owned by the household with the given id.
+    pub fn boat(owner: Option<u32>) -> Building {
+        Building::Boat {
+            has_agent: false,
+            owner,
+            trips: 0,
+            catch: 0,
+        }
+    }
+
+    /// True if this is a boat on the beach that can be used by an agent of
+    /// the household with the given id. With boat ownership agents can only
+    /// use the boats of their household and the boats of nobody.
+    pub fn is_usable_boat(&self, household: Option<u32>, config: &Config) -> bool {
+        match self {
+            Building::Boat {
+                has_agent: false,
+                owner,
+                ..
+            } => !config.boat_ownership || owner.is_none() || *owner == household,
+            _ => false,
         }
     }
 
-    /// Initialize the building (add agent to hut).
+    /// Create an uninitialized hut (without an agent) for the household with
+    /// the given id. Huts should be initialized, by calling initialize later.
+    pub fn hut_uninitialized(id: u32, config: &Config) -> Building {
+        Building::Hut(Household::new(id, config))
+    }
+
+    /// Initialize the building (add agents to hut).
     pub fn initialize(&mut self, pos: Pos, entities: &mut Vec<Entity>, config: &Config) {
         match self {
-            Building::Hut {
-                is_agent_in: _,
-                agent,
-            } if agent.is_uninitialized() => {
-                *agent = EntityId::new(entities.len());
-                let mut a = Agent::new(config);
-                a.in_building = true;
-                a.state = super::agent::AgentState::BeHome;
-                a.home = pos;
+            Building::Hut(h) if h.residents.is_empty() => {
+                let size = thread_rng().gen_range(1..=config.household_size.max(1));
+
+                // Give every resident a different job if jobs are coordinated.
+                let mut jobs = super::agent::Job::choices();
+                jobs.retain(|j| j.is_available(config));
+                jobs.shuffle(&mut thread_rng());
 
-                entities.push(Entity {
-                    pos,
-                    ty: EntityType::Agent(a),
-                })
+                for i in 0..size {
+                    h.residents.push(EntityId::new(entities.len()));
+                    h.inside += 1;
+                    let mut a = Agent::new(config);
+                    a.in_building = true;
+                    a.state = super::agent::AgentState::BeHome;
+                    a.home = pos;
+                    if config.household_jobs && size > 1 && (i as usize) < jobs.len() {
+                        a.job = jobs[i as usize].clone();
+                    }
+
+                    entities.push(Entity {
+                        pos,
+                        ty: EntityType::Agent(Box::new(a)),
+                    })
+                }
             }
             _ => {}
         }
     }
 
     /// This is called when an agent enters a building.
-    pub fn agent_enter(&mut self, id: EntityId) {
+    pub fn agent_enter(&mut self, id: EntityId, a: &mut Agent, config: &Config) {
         match self {
             Building::Market => {}
-            Building::Hut { is_agent_in, agent } => {
-                assert_eq!(*agent, id);
-                *is_agent_in = true;
+            Building::Hut(h) => {
+                assert!(h.residents.contains(&id));
+                h.enter(a, config);
             }
             Building::Boat { .. } => {
                 panic!("Use EnterBoat action to enter a boat!");
             }
+            Building::Site(_) => panic!("Can not enter a construction site!"),
         }
     }
 
     /// This is called when an agent leaves a building.
-    pub fn agent_leave(&mut self, _id: EntityId) {
+    pub fn agent_leave(&mut self, _id: EntityId, a: &mut Agent, config: &Config) {
         match self {
             Building::Market => {}
-            Building::Hut { is_agent_in, .. } => {
-                *is_agent_in = false;
-            }
+            Building::Hut(h) => h.leave(a, config),
             Building::Boat { .. } => {
                 panic!("Use LeaveBoat action to leave a boat!");
             }
+            Building::Site(_) => panic!("Can not leave a construction site!"),
         }
     }
 }
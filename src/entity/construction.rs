@@ -0,0 +1,125 @@
+//! Households construct new buildings using the blueprints in the config. An
+//! agent starts a construction site on a free tile close to its hut, the
+//! residents of the household bring the materials to the site, and once all
+//! materials are there they work on it until the building is finished.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    tile::TileType,
+    world::{Pos, World},
+};
+
+use super::{
+    building::Building,
+    resources::{PerResource, ResourceItem},
+};
+
+/// The kinds of buildings agents can construct.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum BuildingKind {
+    /// An empty hut, children and relocating agents can move in.
+    Hut,
+    /// A market, with local markets it gets its own order book.
+    Market,
+    /// A boat on the beach, owned by the household that constructed it.
+    Boat,
+}
+
+impl BuildingKind {
+    /// True if a building of this kind can be constructed on the given tile.
+    /// Huts and markets are built on grass, boats on the beach.
+    pub fn suitable(self, pos: Pos, world: &World) -> bool {
+        let tile = match self {
+            BuildingKind::Hut | BuildingKind::Market => TileType::Grass,
+            BuildingKind::Boat => TileType::Sand,
+        };
+        world.tile_type(pos) == tile && world.tile_is_walkable(pos)
+    }
+
+    /// The finished building, constructed by the household with the given id.
+    /// A new hut is for the household with the id new_household.
+    pub fn building(self, owner: u32, new_household: u32, config: &Config) -> Building {
+        match self {
+            BuildingKind::Hut => Building::hut_uninitialized(new_household, config),
+            BuildingKind::Market => Building::Market,
+            BuildingKind::Boat => Building::boat(Some(owner)),
+        }
+    }
+}
+
+/// What it takes to construct a building.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Blueprint {
+    /// The kind of building constructed.
+    pub building: BuildingKind,
+    /// Names and amounts of the resources used.
+    pub materials: Vec<(String, u32)>,
+    /// Ticks of work needed once all materials are on the site.
+    pub work: u32,
+    /// The materials found by their names when the config is loaded.
+    #[serde(skip)]
+    material_amounts: Option<PerResource<u32>>,
+}
+
+impl Blueprint {
+    /// Find the materials of this blueprint by their names, this is done once
+    /// when the config is loaded.
+    pub fn resolve(&self, config: &Config) -> std::io::Result<Blueprint> {
+        let mut materials = PerResource::new(0, config);
+        for (name, amount) in &self.materials {
+            let r = ResourceItem::by_name(name, config).ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("Unknown resource {} in blueprint", name),
+                )
+            })?;
+            materials[r] += amount;
+        }
+        Ok(Blueprint {
+            material_amounts: Some(materials),
+            ..self.clone()
+        })
+    }
+
+    /// The amount of every resource used.
+    pub fn materials(&self) -> &PerResource<u32> {
+        self.material_amounts
+            .as_ref()
+            .expect("Blueprint is not resolved")
+    }
+}
+
+/// A building under construction.
+#[derive(Debug, Clone, Hash)]
+pub struct Site {
+    /// Index of the blueprint in the config.
+    pub blueprint: usize,
+    /// The kind of building constructed.
+    pub kind: BuildingKind,
+    /// The id of the household constructing the building.
+    pub owner: u32,
+    /// The materials that still have to be brought to the site.
+    pub missing: PerResource<u32>,
+    /// Ticks of work left until the building is finished.
+    pub work: u32,
+}
+
+impl Site {
+    /// Start constructing the building of the blueprint with the given index.
+    pub fn new(index: usize, blueprint: &Blueprint, owner: u32) -> Site {
+        Site {
+            blueprint: index,
+            kind: blueprint.building,
+            owner,
+            missing: blueprint.materials().clone(),
+            work: blueprint.work,
+        }
+    }
+
+    /// True if all the materials are on the site.
+    pub fn is_supplied(&self) -> bool {
+        self.missing.iter().all(|(_, a)| *a == 0)
+    }
+}
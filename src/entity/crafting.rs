@@ -0,0 +1,88 @@
+//! Crafters turn resources into other resources using the recipes in the
+//! config, for example wood into tools and boats. Crafters buy the inputs and
+//! sell the outputs on the market, like any other resource. Recipes that make
+//! meals are used by cooks instead, and by agents cooking at home.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{config::Config, market::Market};
+
+use super::resources::{Good, PerResource, ResourceItem};
+
+/// A recipe turns the input resources into the output resource.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct Recipe {
+    /// Name of the resource that is made.
+    pub output: String,
+    /// Amount of the output made per craft.
+    pub amount: u32,
+    /// Names and amounts of the resources used per craft.
+    pub inputs: Vec<(String, u32)>,
+    /// The output found by its name when the config is loaded.
+    #[serde(skip)]
+    output_item: Option<ResourceItem>,
+    /// The inputs found by their names when the config is loaded.
+    #[serde(skip)]
+    input_amounts: Option<PerResource<u32>>,
+    /// True if the output is a meal.
+    #[serde(skip)]
+    meal: bool,
+}
+
+impl Recipe {
+    /// Find the output and inputs of this recipe by their names, this is done
+    /// once when the config is loaded.
+    pub fn resolve(&self, config: &Config) -> std::io::Result<Recipe> {
+        let output = find(&self.output, config)?;
+        let mut inputs = PerResource::new(0, config);
+        for (name, amount) in &self.inputs {
+            inputs[find(name, config)?] += amount;
+        }
+        Ok(Recipe {
+            output_item: Some(output),
+            input_amounts: Some(inputs),
+            meal: output.good(config) == Good::Meal,
+            ..self.clone()
+        })
+    }
+
+    /// The resource made by this recipe.
+    pub fn output(&self) -> ResourceItem {
+        self.output_item.expect("Recipe is not resolved")
+    }
+
+    /// The amount of every resource used per craft.
+    pub fn inputs(&self) -> &PerResource<u32> {
+        self.input_amounts.as_ref().expect("Recipe is not resolved")
+    }
+
+    /// True if this recipe makes a meal.
+    pub fn is_meal(&self) -> bool {
+        self.meal
+    }
+
+    /// True if the inventory holds enough resources to craft once.
+    pub fn can_craft(&self, inventory: &PerResource<u32>) -> bool {
+        self.inputs().iter().all(|(r, a)| inventory[r] >= *a)
+    }
+
+    /// The profit of crafting once, at the market prices of the given market.
+    /// An output nobody traded yet is assumed to sell for the price of the
+    /// inputs.
+    pub fn profit(&self, market: &Market) -> i64 {
+        let cost = market.total_price(self.inputs()) as i64;
+        match market.market_price(self.output()) as i64 {
+            0 => 0,
+            price => price * self.amount as i64 - cost,
+        }
+    }
+}
+
+fn find(name: &str, config: &Config) -> std::io::Result<ResourceItem> {
+    ResourceItem::by_name(name, config).ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Unknown resource {} in recipe", name),
+        )
+    })
+}
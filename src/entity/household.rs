@@ -0,0 +1,166 @@
+//! A household is the group of agents living in the same hut. When more than
+//! one agent lives in a hut, the residents put their inventory, and optionally
+//! their cash, in a common pool when they get home. At home they eat from the
+//! common storage, and when they leave they each take an equal share of what
+//! is left.
+//!
+//! With storage enabled every hut has a storage with a limited capacity, and
+//! the residents store and retrieve goods themselves, see AgentState::Store
+//! and AgentState::Fetch.
+
+use crate::config::Config;
+
+use super::{
+    agent::{Agent, Job},
+    resources::PerResource,
+    EntityId,
+};
+
+/// The residents and the shared possessions of a hut.
+#[derive(Debug, Clone, Hash)]
+pub struct Household {
+    /// Identifies the household, the world numbers the households as their
+    /// huts are placed. The id moves along when all residents move to another
+    /// hut together, so the household keeps its boats.
+    pub id: u32,
+    /// The alive agents living in this hut.
+    pub residents: Vec<EntityId>,
+    /// Count of the residents currently in the hut.
+    pub inside: u32,
+    /// Resources shared by the residents.
+    pub storage: PerResource<u32>,
+    /// Cash shared by the residents, only used if cash is pooled.
+    pub cash: u32,
+}
+
+impl Household {
+    /// An empty household, with nothing in storage.
+    pub fn new(id: u32, config: &Config) -> Household {
+        Household {
+            id,
+            residents: Vec::new(),
+            inside: 0,
+            storage: PerResource::new(0, config),
+            cash: 0,
+        }
+    }
+
+    /// True if more than one agent lives here, only then the residents share
+    /// their possessions.
+    pub fn is_shared(&self) -> bool {
+        self.residents.len() > 1
+    }
+
+    /// This is called when a resident enters the hut, it puts its inventory
+    /// and, if cash is pooled, its cash in the common pool. With storage
+    /// enabled the inventory is stored by the agent itself.
+    pub fn enter(&mut self, a: &mut Agent, config: &Config) {
+        self.inside += 1;
+        if !self.is_shared() {
+            return;
+        }
+        if !config.storage {
+            for (r, amount) in a.inventory.iter_mut() {
+                self.storage[r] += *amount;
+                *amount = 0;
+            }
+        }
+        if config.household_pool_cash {
+            self.cash += a.cash;
+            a.cash = 0;
+        }
+    }
+
+    /// This is called when a resident leaves the hut, it takes its share of
+    /// the common pool. The pool is divided by the residents still inside, so
+    /// every resident leaving in the morning gets an equal share.
+    pub fn leave(&mut self, a: &mut Agent, config: &Config) {
+        let inside = self.inside.max(1);
+        self.inside = self.inside.saturating_sub(1);
+        if a.dead {
+            return;
+        }
+        if !config.storage {
+            for (r, amount) in a.inventory.iter_mut() {
+                let share = self.storage[r] / inside;
+                self.storage[r] -= share;
+                *amount += share;
+            }
+        }
+        let share = self.cash / inside;
+        self.cash -= share;
+        a.cash += share;
+    }
+
+    /// The amount of goods that still fit in the storage, this is only
+    /// limited if storage is enabled.
+    pub fn free_space(&self, config: &Config) -> u32 {
+        if !config.storage {
+            return u32::MAX;
+        }
+        let stored = self.storage.iter().map(|(_, a)| *a).sum::<u32>();
+        config.storage_capacity.saturating_sub(stored)
+    }
+
+    /// Select a job none of the other residents has, if the agent has the
+    /// same job as one of them. Returns None if the agent can keep its job.
+    pub fn coordinate_job(job: &Job, others: &[Job], config: &Config) -> Option<Job> {
+        let index = job.choice_index()?;
+        if !others.iter().any(|j| j.choice_index() == Some(index)) {
+            return None;
+        }
+        Job::choices()
+            .into_iter()
+            .filter(|j| j.is_available(config))
+            .find(|j| !others.iter().any(|o| o.choice_index() == j.choice_index()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coordinate_job_keeps_unique_jobs() {
+        let config = Config::default();
+        assert_eq!(
+            Household::coordinate_job(&Job::Farmer, &[Job::Lumberer], &config),
+            None
+        );
+        assert_eq!(Household::coordinate_job(&Job::Farmer, &[], &config), None);
+    }
+
+    #[test]
+    fn coordinate_job_picks_free_job() {
+        let config = Config::default();
+        assert_eq!(
+            Household::coordinate_job(&Job::Farmer, &[Job::Farmer], &config),
+            Some(Job::Lumberer)
+        );
+        // Fishers with and without a boat have the same job.
+        assert_eq!(
+            Household::coordinate_job(
+                &Job::Fisher { boat: None },
+                &[Job::Farmer, Job::Fisher { boat: None }],
+                &config
+            ),
+            Some(Job::Lumberer)
+        );
+    }
+
+    #[test]
+    fn coordinate_job_without_free_job() {
+        // Without recipes there is no crafter or cook to switch to.
+        let config = Config::default();
+        let others = [
+            Job::Farmer,
+            Job::Lumberer,
+            Job::Fisher { boat: None },
+            Job::Butcher,
+        ];
+        assert_eq!(
+            Household::coordinate_job(&Job::Butcher, &others, &config),
+            None
+        );
+    }
+}
@@ -0,0 +1,151 @@
+//! Agents can learn which job suits them best. Every agent keeps an estimate
+//! of the reward of every job, which is updated every time it gets home. When
+//! their quota times out they pick a new job using a multi-armed bandit policy.
+//! Agents also remember how much they harvested doing each job, explorers can
+//! use this to score the jobs by their expected income.
+
+use std::hash::{Hash, Hasher};
+
+use rand::{
+    seq::{IteratorRandom, SliceRandom},
+    thread_rng, Rng,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+use super::{
+    agent::Job,
+    hash_floats,
+    resources::{PerResource, ResourceItem},
+};
+
+/// How agents select a new job when their quota times out.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum JobLearning {
+    /// Become an explorer and pick the job with the most resources around.
+    Explorer,
+    /// Pick a random job with chance epsilon, otherwise the best one.
+    EpsilonGreedy { epsilon: f32 },
+    /// Pick the job with the highest upper confidence bound, the exploration
+    /// constant scales the bonus of rarely tried jobs.
+    Ucb { exploration: f32 },
+}
+
+/// How explorers score the jobs when they pick a new one.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum JobScoring {
+    /// The amount of resources seen while exploring.
+    Observations,
+    /// The expected income of harvesting every resource: the own yields of the
+    /// agent, or the amount seen if it never did the job, times the market
+    /// price, times the demand for the resource relative to the average
+    /// demand. The yields and the amounts seen are each divided by their
+    /// maximum, and the market is the one the agent would trade at.
+    Market,
+}
+
+/// The learned value of every job for one agent.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobValues {
+    /// Estimated reward per trip home for every job, indexed by
+    /// Job::choice_index.
+    pub values: Vec<f32>,
+    /// How many times the agent came home while doing each job.
+    pub counts: Vec<u32>,
+    /// The reward collected since the last update.
+    pub reward: f32,
+    /// Average amount of every resource harvested per trip home, None for the
+    /// resources the agent never harvested.
+    pub yields: PerResource<Option<f32>>,
+    /// The amount of every resource harvested since the last update.
+    pub harvested: PerResource<u32>,
+}
+
+impl JobValues {
+    pub fn new(config: &Config) -> JobValues {
+        JobValues {
+            values: vec![0.; Job::choices().len()],
+            counts: vec![0; Job::choices().len()],
+            reward: 0.,
+            yields: PerResource::new(None, config),
+            harvested: PerResource::new(0, config),
+        }
+    }
+
+    /// Update the value of the given job with the reward collected since the
+    /// last update, and the yields of the resources harvested by the job.
+    /// Rewards collected while exploring are thrown away.
+    pub fn update(&mut self, job: &Job, config: &Config) {
+        if let Some(i) = job.choice_index() {
+            self.values[i] += config.learning_rate * (self.reward - self.values[i]);
+            self.counts[i] += 1;
+        }
+        self.reward = 0.;
+
+        for r in ResourceItem::iterator(config).filter(|r| job.harvests(*r, config)) {
+            let harvested = self.harvested[r] as f32;
+            self.yields[r] = Some(
+                self.yields[r].map_or(harvested, |y| y + config.learning_rate * (harvested - y)),
+            );
+        }
+        self.harvested.take();
+    }
+
+    /// Select the next job using the given policy, among the jobs available
+    /// with the given config.
+    pub fn select(&self, policy: JobLearning, config: &Config) -> Job {
+        let mut rng = thread_rng();
+        let mut choices = Job::choices();
+        let available = (0..choices.len())
+            .filter(|i| choices[*i].is_available(config))
+            .collect::<Vec<_>>();
+        let best = |score: &dyn Fn(usize) -> f32| {
+            *available
+                .iter()
+                .max_by(|a, b| score(**a).partial_cmp(&score(**b)).unwrap())
+                .unwrap()
+        };
+
+        let i = match policy {
+            JobLearning::Explorer => unreachable!("Explorers do not learn"),
+            JobLearning::EpsilonGreedy { epsilon } => {
+                if rng.gen::<f32>() < epsilon {
+                    *available.choose(&mut rng).unwrap()
+                } else {
+                    best(&|i| self.values[i])
+                }
+            }
+            JobLearning::Ucb { exploration } => {
+                // Try every job once before trusting the estimates.
+                if let Some(i) = available
+                    .iter()
+                    .copied()
+                    .filter(|i| self.counts[*i] == 0)
+                    .choose(&mut rng)
+                {
+                    i
+                } else {
+                    let total = self.counts.iter().sum::<u32>() as f32;
+                    best(&|i| {
+                        self.values[i] + exploration * (total.ln() / self.counts[i] as f32).sqrt()
+                    })
+                }
+            }
+        };
+
+        choices.swap_remove(i)
+    }
+}
+
+impl Hash for JobValues {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_floats(&self.values, state);
+        self.counts.hash(state);
+        hash_floats(&[self.reward], state);
+        for (_, y) in self.yields.iter() {
+            y.map(f32::to_bits).hash(state);
+        }
+        self.harvested.hash(state);
+    }
+}
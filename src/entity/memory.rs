@@ -0,0 +1,332 @@
+//! Agents remember what they have seen. Every step an agent looks at all the
+//! tiles within its vision radius and remembers the resources, markets and
+//! boats it sees. When nothing is in sight, agents plan from their memory.
+//! With gossip enabled agents also learn from the memory of other agents.
+
+use rand::{thread_rng, Rng};
+
+use crate::{
+    config::Config,
+    market::Market,
+    world::{Pos, World},
+};
+
+use super::{
+    agent::JobKind,
+    building::Building,
+    resources::{PerResource, ResourceItem},
+    EntityType,
+};
+
+/// Everything an agent remembers about the world.
+#[derive(Debug, Clone, Hash, Default)]
+pub struct Memory {
+    /// Resources the agent has seen.
+    pub resources: Vec<ResourceMemory>,
+    /// Markets the agent has seen.
+    pub markets: Vec<MarketMemory>,
+    /// Boats the agent has seen on the beach.
+    pub boats: Vec<Pos>,
+}
+
+/// A resource tile the agent has seen.
+#[derive(Debug, Clone, Hash)]
+pub struct ResourceMemory {
+    pub pos: Pos,
+    pub item: ResourceItem,
+    /// The amount that was available when last seen.
+    pub amount: u16,
+    /// The tick the resource was last seen.
+    pub seen: u32,
+    /// If the resource was depleted, the tick it is expected to respawn.
+    pub respawn: u32,
+    /// Through how many agents this information was passed, zero if the
+    /// agent saw the resource itself.
+    pub hops: u32,
+}
+
+/// A market the agent has seen.
+#[derive(Debug, Clone, Hash)]
+pub struct MarketMemory {
+    pub pos: Pos,
+    /// The market prices the agent saw the last time it visited this market,
+    /// or None if it never visited it.
+    pub prices: Option<PerResource<u32>>,
+    /// The amount of every resource offered on this market the last time the
+    /// agent visited it, or None if it never visited it.
+    pub depth: Option<PerResource<u32>>,
+    /// The tick the prices were seen.
+    pub seen: u32,
+    /// Through how many agents the prices were passed, zero if the agent saw
+    /// them itself.
+    pub hops: u32,
+}
+
+impl Memory {
+    /// Look at all the tiles in the vision radius and update the memory.
+    /// Only boats an agent of the given household can use are remembered.
+    pub fn observe(&mut self, pos: Pos, household: Option<u32>, world: &World) {
+        let diameter = world.config.vision_radius * 2 + 1;
+        world.find_tile_around(pos, diameter * diameter, |p| {
+            match world.entity_at(p).map(|e| &e.ty) {
+                Some(EntityType::Resource(r)) => {
+                    self.see_resource(p, r.product(), r.available(), world)
+                }
+                Some(EntityType::Building(Building::Market)) => self.see_market(p),
+                Some(EntityType::Building(b @ Building::Boat { .. }))
+                    if b.is_usable_boat(household, &world.config) =>
+                {
+                    if !self.boats.contains(&p) {
+                        self.boats.push(p);
+                    }
+                }
+                _ => {
+                    // Remembered boats might have been taken, and remembered
+                    // resources might have been depleted.
+                    self.boats.retain(|b| *b != p);
+                    if let Some(r) = self.resources.iter_mut().find(|r| r.pos == p) {
+                        if r.amount > 0 || r.respawn <= world.tick {
+                            r.amount = 0;
+                            r.respawn = world.tick + world.config.resource_timeout as u32;
+                        }
+                    }
+                }
+            }
+            false
+        });
+    }
+
+    fn see_resource(&mut self, pos: Pos, item: ResourceItem, amount: u16, world: &World) {
+        // Empty resources are removed from the map soon, and respawn later.
+        let respawn = if amount == 0 {
+            world.tick + world.config.resource_timeout as u32
+        } else {
+            world.tick
+        };
+        let memory = ResourceMemory {
+            pos,
+            item,
+            amount,
+            seen: world.tick,
+            respawn,
+            hops: 0,
+        };
+        self.insert_resource(memory, &world.config);
+    }
+
+    /// Insert or replace the memory of a resource.
+    fn insert_resource(&mut self, memory: ResourceMemory, config: &Config) {
+        match self.resources.iter_mut().find(|r| r.pos == memory.pos) {
+            Some(r) => *r = memory,
+            None => {
+                // Forget the resource we have not seen for the longest time.
+                if self.resources.len() >= config.memory_capacity {
+                    let oldest = (0..self.resources.len())
+                        .min_by_key(|i| self.resources[*i].seen)
+                        .unwrap();
+                    self.resources.swap_remove(oldest);
+                }
+                self.resources.push(memory)
+            }
+        }
+    }
+
+    fn see_market(&mut self, pos: Pos) {
+        if !self.markets.iter().any(|m| m.pos == pos) {
+            self.markets.push(MarketMemory {
+                pos,
+                prices: None,
+                depth: None,
+                seen: 0,
+                hops: 0,
+            });
+        }
+    }
+
+    /// Save the current prices and order depth of the market at the given
+    /// position.
+    pub fn remember_prices(&mut self, pos: Pos, market: &Market, tick: u32) {
+        self.see_market(pos);
+        let prices = market.market_price.map(|p| *p as u32);
+        let depth = market.volume();
+        for m in &mut self.markets {
+            if m.pos == pos {
+                m.prices = Some(prices.clone());
+                m.depth = Some(depth.clone());
+                m.seen = tick;
+                m.hops = 0;
+            }
+        }
+    }
+
+    /// Learn from the memory of another agent. Every resource and market the
+    /// other agent saw more recently is passed on with a chance of
+    /// gossip_fidelity. Returns the position and hops of everything learned.
+    pub fn learn(&mut self, other: &Memory, config: &Config) -> Vec<(Pos, u32)> {
+        let mut rng = thread_rng();
+        let mut learned = Vec::new();
+
+        for r in &other.resources {
+            let newer = match self.resources.iter().find(|m| m.pos == r.pos) {
+                Some(m) => m.seen < r.seen,
+                None => true,
+            };
+            if newer && rng.gen::<f32>() < config.gossip_fidelity {
+                let hops = r.hops + 1;
+                self.insert_resource(ResourceMemory { hops, ..r.clone() }, config);
+                learned.push((r.pos, hops));
+            }
+        }
+
+        for m in &other.markets {
+            let newer = match self.markets.iter().find(|k| k.pos == m.pos) {
+                Some(k) => k.seen < m.seen,
+                None => true,
+            };
+            if newer && rng.gen::<f32>() < config.gossip_fidelity {
+                let hops = m.hops + 1;
+                self.markets.retain(|k| k.pos != m.pos);
+                self.markets.push(MarketMemory { hops, ..m.clone() });
+                learned.push((m.pos, hops));
+            }
+        }
+
+        learned
+    }
+
+    /// The remembered prices of the market at the given position.
+    pub fn prices(&self, pos: Pos) -> Option<&PerResource<u32>> {
+        self.markets.iter().find(|m| m.pos == pos)?.prices.as_ref()
+    }
+
+    /// The remembered order depth of the market at the given position.
+    pub fn depth(&self, pos: Pos) -> Option<&PerResource<u32>> {
+        self.markets.iter().find(|m| m.pos == pos)?.depth.as_ref()
+    }
+
+    /// The closest remembered resource harvested by the given job, that should
+    /// be available by now.
+    pub fn nearest_resource(&self, pos: Pos, job: JobKind, world: &World) -> Option<Pos> {
+        self.resources
+            .iter()
+            .filter(|r| {
+                r.item.job(&world.config) == Some(job) && (r.amount > 0 || r.respawn <= world.tick)
+            })
+            .min_by_key(|r| r.pos.distance(pos, world))
+            .map(|r| r.pos)
+    }
+
+    /// The closest remembered market.
+    pub fn nearest_market(&self, pos: Pos, world: &World) -> Option<Pos> {
+        self.markets
+            .iter()
+            .min_by_key(|m| m.pos.distance(pos, world))
+            .map(|m| m.pos)
+    }
+
+    /// The closest remembered boat.
+    pub fn nearest_boat(&self, pos: Pos, world: &World) -> Option<Pos> {
+        self.boats
+            .iter()
+            .min_by_key(|b| b.distance(pos, world))
+            .copied()
+    }
+
+    /// Forget everything about the given position, this is used when the
+    /// agent reaches a remembered position and there is nothing there.
+    pub fn forget(&mut self, pos: Pos) {
+        self.resources.retain(|r| r.pos != pos);
+        self.boats.retain(|b| *b != pos);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource(x: i16, seen: u32, hops: u32) -> ResourceMemory {
+        ResourceMemory {
+            pos: Pos { x, y: 0 },
+            item: ResourceItem::from_index(0),
+            amount: 10,
+            seen,
+            respawn: seen,
+            hops,
+        }
+    }
+
+    fn market(x: i16, seen: u32) -> MarketMemory {
+        MarketMemory {
+            pos: Pos { x, y: 0 },
+            prices: None,
+            depth: None,
+            seen,
+            hops: 0,
+        }
+    }
+
+    #[test]
+    fn learn_newer_memories() {
+        let config = Config {
+            gossip_fidelity: 1.,
+            ..Config::default()
+        };
+        let mut memory = Memory {
+            resources: vec![resource(0, 10, 0), resource(1, 10, 0)],
+            markets: vec![market(5, 10)],
+            boats: Vec::new(),
+        };
+        let other = Memory {
+            resources: vec![resource(0, 20, 0), resource(1, 5, 0), resource(2, 5, 2)],
+            markets: vec![market(5, 5), market(6, 5)],
+            boats: Vec::new(),
+        };
+
+        // Only what the other agent saw more recently is learned, one hop
+        // further away from the agent that saw it.
+        let mut learned = memory.learn(&other, &config);
+        learned.sort_by_key(|(p, _)| p.x);
+        assert_eq!(
+            learned,
+            vec![
+                (Pos { x: 0, y: 0 }, 1),
+                (Pos { x: 2, y: 0 }, 3),
+                (Pos { x: 6, y: 0 }, 1)
+            ]
+        );
+        let seen = memory
+            .resources
+            .iter()
+            .map(|r| (r.pos.x, r.seen, r.hops))
+            .collect::<Vec<_>>();
+        assert_eq!(seen, vec![(0, 20, 1), (1, 10, 0), (2, 5, 3)]);
+        assert_eq!(memory.markets.len(), 2);
+        assert_eq!(memory.markets[0].seen, 10);
+    }
+
+    #[test]
+    fn learn_respects_capacity_and_fidelity() {
+        let config = Config {
+            gossip_fidelity: 1.,
+            memory_capacity: 2,
+            ..Config::default()
+        };
+        let mut memory = Memory::default();
+        let other = Memory {
+            resources: vec![resource(0, 3, 0), resource(1, 1, 0), resource(2, 2, 0)],
+            ..Memory::default()
+        };
+        memory.learn(&other, &config);
+        let mut kept = memory.resources.iter().map(|r| r.pos.x).collect::<Vec<_>>();
+        kept.sort_unstable();
+        assert_eq!(kept, vec![0, 2]);
+
+        let config = Config {
+            gossip_fidelity: 0.,
+            ..Config::default()
+        };
+        let mut memory = Memory::default();
+        assert!(memory.learn(&other, &config).is_empty());
+        assert!(memory.resources.is_empty());
+    }
+}
@@ -1,18 +1,30 @@
 // TODO: For every company/resource ID they meet, they add them to their table of
 //       met entities and calculate the reward expectation using an RL algorithm.
+//       For now agents only learn the reward expectation per job, see
+//       learning.rs.
 
-use std::num::NonZeroU32;
+use std::{
+    hash::{Hash, Hasher},
+    num::NonZeroU32,
+};
 
 pub mod agent;
+pub mod brain;
 pub mod building;
+pub mod construction;
+pub mod crafting;
+pub mod household;
+pub mod learning;
+pub mod memory;
 pub mod resources;
+pub mod skill;
 
 use crate::{config::Config, world::Pos};
 
 use self::{
     agent::{Agent, AgentState},
     building::Building,
-    resources::{Resource, ResourceItem},
+    resources::Resource,
 };
 
 /// This is like a reference to an entity. it contains the index into the entity
@@ -40,6 +52,13 @@ impl EntityId {
     }
 }
 
+/// Floats do not implement Hash, so entities with floats hash their bits.
+pub fn hash_floats<H: Hasher>(floats: &[f32], state: &mut H) {
+    for f in floats {
+        f.to_bits().hash(state);
+    }
+}
+
 /// In our world everything that is not a tile is an entity. Every entity is on
 /// exactly one or zero tiles. ANd we have a two way mapping from tile to entity
 /// and from entity to tile. An entity can either be an agent, a resource, or a
@@ -54,7 +73,7 @@ pub struct Entity {
 
 impl Entity {
     /// The texture index of this entity.
-    pub fn texture(&self) -> i32 {
+    pub fn texture(&self, config: &Config) -> i32 {
         match &self.ty {
             EntityType::Agent(a) => {
                 if matches!(a.state, AgentState::DoJob)
@@ -66,44 +85,29 @@ impl Entity {
                 }
             }
             EntityType::Building(Building::Market) => 56,
-            EntityType::Building(Building::Hut { .. }) => 57,
+            EntityType::Building(Building::Hut(_)) => 57,
             EntityType::Building(Building::Boat { .. }) => 49,
-            EntityType::Resource(Resource {
-                resource: ResourceItem::Wheat,
-                ..
-            }) => 32,
-            EntityType::Resource(Resource {
-                resource: ResourceItem::Berry,
-                ..
-            }) => 33,
-            EntityType::Resource(Resource {
-                resource: ResourceItem::Meat,
-                ..
-            }) => 40,
-            EntityType::Resource(Resource {
-                resource: ResourceItem::Fish,
-                ..
-            }) => 50,
+            EntityType::Building(Building::Site(_)) => 58,
+            EntityType::Resource(r) => r.product().texture(config),
         }
     }
 
     /// True if this entity is currently visible.
     pub fn visible(&self) -> bool {
         match self.ty {
-            EntityType::Agent(Agent {
-                in_building, dead, ..
-            }) => !(in_building || dead),
-            EntityType::Building(Building::Boat { has_agent }) => !has_agent,
+            EntityType::Agent(ref a) => !(a.in_building || a.dead),
+            EntityType::Building(Building::Boat { has_agent, .. }) => !has_agent,
             EntityType::Resource(Resource { timeout, .. }) => timeout == 0,
             _ => true,
         }
     }
 }
 
-/// The type of this entity. For more information see Entity.
+/// The type of this entity. For more information see Entity. Agents are
+/// much larger than resources and buildings, so they are boxed.
 #[derive(Debug, Clone, Hash)]
 pub enum EntityType {
-    Agent(Agent),
+    Agent(Box<Agent>),
     Resource(Resource),
     Building(Building),
 }
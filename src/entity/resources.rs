@@ -1,10 +1,14 @@
 //! Resources are the source of basically all value im our simulation. Resources
 //! spawn in the world when it is generated. After a resource has been depleted
-//! it will respawn at the same space after some time
+//! it will respawn at the same space after some time. The kinds of resources
+//! are defined in the config, see [`ResourceConfig`].
 
-use crate::entity::agent::Agent;
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::entity::agent::{Agent, JobKind};
+use crate::generation::BiomeKind;
 use crate::market::Market;
-use std::slice::Iter;
 
 // This is a resource as it is in the world.
 #[derive(Debug, Clone, Hash, PartialEq)]
@@ -46,33 +50,85 @@ impl Resource {
     }
 }
 
-/// These are the four different kind of resources in the world
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
-pub enum ResourceItem {
-    Wheat,
-    Berry,
-    Fish,
-    Meat,
+/// Everything about one kind of resource, the kinds of resources are defined
+/// in the config.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ResourceConfig {
+    /// Name of the resource, this is used in the exported statistics.
+    pub name: String,
+    /// Nutritional value of one unit for a new agent.
+    pub nutrition: u8,
+    /// Index of the sprite of the resource.
+    pub texture: i32,
+    /// The biomes this resource spawns in.
+    pub spawns: Vec<ResourceSpawn>,
+    /// The job that harvests this resource, None for resources that do not
+    /// spawn in the world, like meals, tools and boats.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub job: Option<JobKind>,
+    /// What the resource is used for.
+    pub good: Good,
+}
+
+/// What a kind of resource is used for.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize)]
+pub enum Good {
+    /// Food can be eaten.
+    Food,
+    /// Meals are food cooked with a recipe. Eating a meal restores the
+    /// nutritional value of its ingredients to at least their initial value.
+    Meal,
+    /// Materials are only used in recipes.
+    Material,
+    /// Harvesters with a tool get an extra unit with a chance of bonus every
+    /// time they farm, the tool breaks after the given amount of uses.
+    Tool { uses: u32, bonus: f32 },
+    /// Fishers can put a boat on the beach.
+    Boat,
+}
+
+/// Spawns a resource in a biome, the weight is relative to the weights of the
+/// other tiles of the biome, see generation.rs.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct ResourceSpawn {
+    pub biome: BiomeKind,
+    pub weight: u16,
+}
+
+impl ResourceConfig {
+    /// The four resources of the original simulation.
+    pub fn defaults() -> Vec<ResourceConfig> {
+        let resource = |name: &str, texture, biome, weight, job| ResourceConfig {
+            name: name.to_string(),
+            nutrition: 100,
+            texture,
+            spawns: vec![ResourceSpawn { biome, weight }],
+            job: Some(job),
+            good: Good::Food,
+        };
+        vec![
+            resource("wheat", 32, BiomeKind::Grass, 30, JobKind::Farmer),
+            resource("berry", 33, BiomeKind::Grass, 30, JobKind::Lumberer),
+            resource("fish", 50, BiomeKind::Ocean, 20, JobKind::Fisher),
+            resource("meat", 40, BiomeKind::Grass, 30, JobKind::Butcher),
+        ]
+    }
 }
 
+/// A kind of resource, this is an index into the resources in the config.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ResourceItem(u8);
+
 impl ResourceItem {
-    pub fn iterator() -> Iter<'static, ResourceItem> {
-        static RESOURCE_ITEMS: [ResourceItem; 4] = [
-            ResourceItem::Wheat,
-            ResourceItem::Berry,
-            ResourceItem::Fish,
-            ResourceItem::Meat,
-        ];
-        RESOURCE_ITEMS.iter()
-    }
-
-    pub fn sorted(agent: &Agent, market: &Market) -> [ResourceItem; 4] {
-        let mut resource_item: [ResourceItem; 4] = [
-            ResourceItem::Wheat,
-            ResourceItem::Berry,
-            ResourceItem::Fish,
-            ResourceItem::Meat,
-        ];
+    /// Iterate over all kinds of resources of the config.
+    pub fn iterator(config: &Config) -> impl ExactSizeIterator<Item = ResourceItem> {
+        (0..config.resources.len()).map(|i| ResourceItem(i as u8))
+    }
+
+    /// All kinds of resources, sorted by the energy the agent gets from them
+    /// per unit of cash on the market. The best deal comes first.
+    pub fn sorted(agent: &Agent, market: &Market) -> Vec<ResourceItem> {
+        let mut resource_item = agent.nutrition.keys().collect::<Vec<_>>();
 
         resource_item.sort_by_key(|r| {
             let projected_price = market.market_price(*r);
@@ -84,66 +140,119 @@ impl ResourceItem {
         resource_item
     }
 
+    /// The resource with the given index, the index has to be smaller than the
+    /// amount of resources in the config.
     pub fn from_index(index: u8) -> ResourceItem {
-        match index {
-            0 => ResourceItem::Wheat,
-            1 => ResourceItem::Berry,
-            2 => ResourceItem::Fish,
-            3 => ResourceItem::Meat,
-            _ => panic!(
-                "Index {} out of bounds when trying to access a ResourceItem",
-                index
-            ),
-        }
+        ResourceItem(index)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    /// Find a kind of resource by its name.
+    pub fn by_name(name: &str, config: &Config) -> Option<ResourceItem> {
+        config
+            .resources
+            .iter()
+            .position(|r| r.name == name)
+            .map(|i| ResourceItem(i as u8))
+    }
+
+    /// Everything the config says about this kind of resource.
+    pub fn config(self, config: &Config) -> &ResourceConfig {
+        &config.resources[self.index()]
+    }
+
+    /// The name of this kind of resource.
+    pub fn name(self, config: &Config) -> &str {
+        &self.config(config).name
+    }
+
+    /// Index of the sprite of this resource.
+    pub fn texture(self, config: &Config) -> i32 {
+        self.config(config).texture
+    }
+
+    /// The job that harvests this resource, if it is harvested.
+    pub fn job(self, config: &Config) -> Option<JobKind> {
+        self.config(config).job
+    }
+
+    /// What this resource is used for.
+    pub fn good(self, config: &Config) -> Good {
+        self.config(config).good
+    }
+
+    /// True if this resource can be eaten.
+    pub fn is_food(self, config: &Config) -> bool {
+        matches!(self.good(config), Good::Food | Good::Meal)
+    }
+
+    /// The first kind of tool, if there are tools.
+    pub fn tool(config: &Config) -> Option<ResourceItem> {
+        Self::iterator(config).find(|r| matches!(r.good(config), Good::Tool { .. }))
+    }
+
+    /// The first kind of boat, if boats can be crafted.
+    pub fn boat(config: &Config) -> Option<ResourceItem> {
+        Self::iterator(config).find(|r| r.good(config) == Good::Boat)
+    }
+
+    /// The first kind of resource harvested by the given job.
+    pub fn harvested_by(job: JobKind, config: &Config) -> Option<ResourceItem> {
+        Self::iterator(config).find(|r| r.job(config) == Some(job))
     }
 }
 
 /// Throughout our codebase we often need to store some information for every
-/// resource, this struct helps a lot with that.
-#[derive(Debug, Clone, Hash, Default, PartialEq, Eq)]
-pub struct PerResource<T> {
-    pub wheat: T,
-    pub berry: T,
-    pub fish: T,
-    pub meat: T,
-}
+/// resource, this struct helps a lot with that. It holds one value for every
+/// kind of resource in the config, indexed by ResourceItem.
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct PerResource<T>(Vec<T>);
 
 impl<T: Clone> PerResource<T> {
-    pub fn new(val: T) -> PerResource<T> {
-        PerResource {
-            wheat: val.clone(),
-            berry: val.clone(),
-            fish: val.clone(),
-            meat: val,
-        }
+    /// The same value for every resource of the config.
+    pub fn new(val: T, config: &Config) -> PerResource<T> {
+        PerResource(vec![val; config.resources.len()])
+    }
+}
+
+impl<T: Default> PerResource<T> {
+    /// Take all values, leaving the default values in their place.
+    pub fn take(&mut self) -> PerResource<T> {
+        PerResource(self.0.iter_mut().map(std::mem::take).collect())
     }
 }
 
 impl<T> PerResource<T> {
+    /// Create a value for every resource of the config with a function.
+    pub fn from_fn(config: &Config, f: impl FnMut(ResourceItem) -> T) -> PerResource<T> {
+        PerResource(ResourceItem::iterator(config).map(f).collect())
+    }
+
+    /// All kinds of resources, in the same order as the values.
+    pub fn keys(&self) -> impl ExactSizeIterator<Item = ResourceItem> {
+        (0..self.0.len()).map(|i| ResourceItem(i as u8))
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (ResourceItem, &T)> {
-        use std::iter::once;
-        once((ResourceItem::Wheat, &self.wheat))
-            .chain(once((ResourceItem::Berry, &self.berry)))
-            .chain(once((ResourceItem::Fish, &self.fish)))
-            .chain(once((ResourceItem::Meat, &self.meat)))
+        self.0
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (ResourceItem(i as u8), v))
     }
 
     pub fn iter_mut(&mut self) -> impl Iterator<Item = (ResourceItem, &mut T)> + '_ {
-        use std::iter::once;
-        once((ResourceItem::Wheat, &mut self.wheat))
-            .chain(once((ResourceItem::Berry, &mut self.berry)))
-            .chain(once((ResourceItem::Fish, &mut self.fish)))
-            .chain(once((ResourceItem::Meat, &mut self.meat)))
+        self.0
+            .iter_mut()
+            .enumerate()
+            .map(|(i, v)| (ResourceItem(i as u8), v))
     }
 
     /// Transform all values in this struct.
-    pub fn map<U>(&self, mut f: impl FnMut(&T) -> U) -> PerResource<U> {
-        PerResource {
-            wheat: f(&self.wheat),
-            berry: f(&self.berry),
-            fish: f(&self.fish),
-            meat: f(&self.meat),
-        }
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> PerResource<U> {
+        PerResource(self.0.iter().map(f).collect())
     }
 
     /// Like map but for two PerResources.
@@ -152,67 +261,37 @@ impl<T> PerResource<T> {
         other: &PerResource<V>,
         mut f: impl FnMut(&T, &V) -> U,
     ) -> PerResource<U> {
-        PerResource {
-            wheat: f(&self.wheat, &other.wheat),
-            berry: f(&self.berry, &other.berry),
-            fish: f(&self.fish, &other.fish),
-            meat: f(&self.meat, &other.meat),
-        }
+        PerResource(self.0.iter().zip(&other.0).map(|(a, b)| f(a, b)).collect())
     }
-}
 
-impl<T> std::ops::Index<ResourceItem> for PerResource<T> {
-    type Output = T;
-
-    fn index(&self, index: ResourceItem) -> &Self::Output {
-        match index {
-            ResourceItem::Wheat => &self.wheat,
-            ResourceItem::Berry => &self.berry,
-            ResourceItem::Fish => &self.fish,
-            ResourceItem::Meat => &self.meat,
-        }
+    /// Show the values with the names of the resources.
+    pub fn named<'a>(&'a self, config: &'a Config) -> Named<'a, T> {
+        Named(self, config)
     }
 }
 
-impl<T> std::ops::IndexMut<ResourceItem> for PerResource<T> {
-    fn index_mut(&mut self, index: ResourceItem) -> &mut Self::Output {
-        match index {
-            ResourceItem::Wheat => &mut self.wheat,
-            ResourceItem::Berry => &mut self.berry,
-            ResourceItem::Fish => &mut self.fish,
-            ResourceItem::Meat => &mut self.meat,
-        }
+/// Shows the values of a PerResource with the names of the resources, see
+/// PerResource::named.
+pub struct Named<'a, T>(&'a PerResource<T>, &'a Config);
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Named<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.0.iter().map(|(r, v)| (r.name(self.1), v)))
+            .finish()
     }
 }
 
-impl<T> std::ops::Index<u8> for PerResource<T> {
+impl<T> std::ops::Index<ResourceItem> for PerResource<T> {
     type Output = T;
 
-    fn index(&self, index: u8) -> &Self::Output {
-        match index {
-            0 => &self.wheat,
-            1 => &self.berry,
-            2 => &self.fish,
-            3 => &self.meat,
-            _ => panic!(
-                "Index {} out of bounds when trying to access a ResourceItem",
-                index
-            ),
-        }
+    fn index(&self, index: ResourceItem) -> &Self::Output {
+        &self.0[index.index()]
     }
 }
 
-impl<T> std::ops::IndexMut<u8> for PerResource<T> {
-    fn index_mut(&mut self, index: u8) -> &mut Self::Output {
-        match index {
-            0 => &mut self.wheat,
-            1 => &mut self.berry,
-            2 => &mut self.fish,
-            3 => &mut self.meat,
-            _ => panic!(
-                "Index {} out of bounds when trying to access a ResourceItem",
-                index
-            ),
-        }
+impl<T> std::ops::IndexMut<ResourceItem> for PerResource<T> {
+    fn index_mut(&mut self, index: ResourceItem) -> &mut Self::Output {
+        &mut self.0[index.index()]
     }
 }
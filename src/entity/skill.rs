@@ -0,0 +1,62 @@
+//! Agents get better at a job by doing it. Every successful farm action
+//! increases the skill of the current job, while the skills of all other jobs
+//! slowly decay. The skill determines the chance that farming succeeds, so
+//! switching jobs has a cost.
+
+use std::hash::{Hash, Hasher};
+
+use crate::config::Config;
+
+use super::{agent::Job, hash_floats};
+
+/// The skill level of an agent for every job.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Skills {
+    /// Skill between zero and one for every job, indexed by
+    /// Job::choice_index.
+    pub levels: Vec<f32>,
+}
+
+impl Skills {
+    pub fn new(config: &Config) -> Skills {
+        Skills {
+            levels: vec![config.initial_skill; Job::choices().len()],
+        }
+    }
+
+    /// The skill of the given job, explorers have no skill.
+    pub fn level(&self, job: &Job) -> f32 {
+        job.choice_index().map_or(0., |i| self.levels[i])
+    }
+
+    /// The chance that farming succeeds while doing the given job. This goes
+    /// linearly from unskilled_yield at skill zero to one at skill one.
+    pub fn success_chance(&self, job: &Job, config: &Config) -> f32 {
+        config.unskilled_yield + (1. - config.unskilled_yield) * self.level(job)
+    }
+
+    /// Increase the skill of the given job after farming successfully. The
+    /// gain gets smaller when the skill approaches one.
+    pub fn practice(&mut self, job: &Job, config: &Config) {
+        if let Some(i) = job.choice_index() {
+            self.levels[i] += config.skill_gain * (1. - self.levels[i]);
+        }
+    }
+
+    /// Decay the skills of all jobs except the given one, this should be called
+    /// every tick.
+    pub fn decay(&mut self, job: &Job, config: &Config) {
+        let current = job.choice_index();
+        for (i, level) in self.levels.iter_mut().enumerate() {
+            if Some(i) != current {
+                *level *= 1. - config.skill_decay;
+            }
+        }
+    }
+}
+
+impl Hash for Skills {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        hash_floats(&self.levels, state);
+    }
+}
@@ -5,6 +5,7 @@ use rand::{
     distributions::{Distribution, WeightedIndex},
     Rng,
 };
+use serde::{Deserialize, Serialize};
 
 use crate::{
     config::Config,
@@ -17,9 +18,21 @@ use crate::{
     world::Pos,
 };
 
+/// The different kinds of biomes, this is used in the config to tell in which
+/// biomes a resource spawns.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum BiomeKind {
+    Ocean,
+    Beach,
+    Grass,
+    Desert,
+    HighLands,
+}
+
 /// A biome consists of a tile distribution. And a score function that
 /// determines when this biome will be selected.
 pub struct Biome {
+    pub kind: BiomeKind,
     /// Distribution of tiles for this biome.
     tiles: TileDistribution,
     /// To select what biome goes where we use two noise maps, one containing
@@ -33,6 +46,7 @@ impl Biome {
     pub fn ocean(config: &Config) -> Biome {
         let ocean_cutoff = config.ocean_cutoff;
         Biome {
+            kind: BiomeKind::Ocean,
             tiles: TileDistribution::ocean(config),
             score_fn: Box::new(move |elevation, _climate| {
                 (elevation < ocean_cutoff) as isize * 10000
@@ -42,7 +56,8 @@ impl Biome {
     pub fn beach(config: &Config) -> Biome {
         let beach_cutoff = config.beach_cutoff;
         Biome {
-            tiles: TileDistribution::beach(),
+            kind: BiomeKind::Beach,
+            tiles: TileDistribution::beach(config),
             score_fn: Box::new(move |elevation, _climate| {
                 (elevation < beach_cutoff) as isize * 9000
             }),
@@ -50,19 +65,22 @@ impl Biome {
     }
     pub fn grass(config: &Config) -> Biome {
         Biome {
+            kind: BiomeKind::Grass,
             tiles: TileDistribution::grass(config),
             score_fn: Box::new(|_elevation, climate| 1000 - climate.abs()),
         }
     }
-    pub fn desert() -> Biome {
+    pub fn desert(config: &Config) -> Biome {
         Biome {
-            tiles: TileDistribution::desert(),
+            kind: BiomeKind::Desert,
+            tiles: TileDistribution::desert(config),
             score_fn: Box::new(|_elevation, climate| climate * 2 + 500),
         }
     }
-    pub fn high_lands() -> Biome {
+    pub fn high_lands(config: &Config) -> Biome {
         Biome {
-            tiles: TileDistribution::high_lands(),
+            kind: BiomeKind::HighLands,
+            tiles: TileDistribution::high_lands(config),
             score_fn: Box::new(|elevation, climate| 500 - climate.abs() + elevation),
         }
     }
@@ -97,15 +115,17 @@ impl BiomeMap {
                 Biome::ocean(config),
                 Biome::grass(config),
                 Biome::beach(config),
-                Biome::desert(),
-                Biome::high_lands(),
+                Biome::desert(config),
+                Biome::high_lands(config),
             ],
             elevation,
             climate,
         }
     }
 
-    pub fn get(&self, p: Pos, rng: &mut impl Rng) -> (TileType, Option<EntityType>) {
+    /// Generate the tile at the given position, returns the biome, the tile
+    /// type and the entity on the tile.
+    pub fn get(&self, p: Pos, rng: &mut impl Rng) -> (BiomeKind, TileType, Option<EntityType>) {
         let pos = [p.x as f64 / 5., p.y as f64 / 5.];
         let elevation = (self.elevation.iter().map(|e| e.get(pos)).sum::<f64>() * 500.) as isize;
         let climate = (self.climate.iter().map(|e| e.get(pos)).sum::<f64>() * 500.) as isize;
@@ -115,7 +135,8 @@ impl BiomeMap {
             .max_by_key(|b| (b.score_fn)(elevation, climate))
             .unwrap();
 
-        b.tiles.sample(rng)
+        let (tile, entity) = b.tiles.sample(rng);
+        (b.kind, tile, entity)
     }
 }
 
@@ -126,102 +147,106 @@ pub struct TileDistribution {
 }
 
 impl TileDistribution {
-    pub fn grass(config: &Config) -> TileDistribution {
+    /// Create the distribution of a biome from its base tiles, the resources
+    /// that spawn in this biome are added on top of the first tile.
+    fn new(
+        biome: BiomeKind,
+        mut tiles: Vec<(TileType, Option<EntityType>)>,
+        mut weights: Vec<u16>,
+        config: &Config,
+    ) -> TileDistribution {
+        let base = tiles[0].0;
+        for (i, r) in config.resources.iter().enumerate() {
+            for spawn in r.spawns.iter().filter(|s| s.biome == biome) {
+                tiles.push((
+                    base,
+                    Some(EntityType::Resource(Resource::new(
+                        ResourceItem::from_index(i as u8),
+                        config.resource_amount_mean as u16,
+                    ))),
+                ));
+                weights.push(spawn.weight);
+            }
+        }
         TileDistribution {
-            tiles: vec![
+            tiles,
+            weights: WeightedIndex::new(&weights).unwrap(),
+        }
+    }
+
+    pub fn grass(config: &Config) -> TileDistribution {
+        TileDistribution::new(
+            BiomeKind::Grass,
+            vec![
                 (TileType::Grass, None),
                 (TileType::GrassRock, None),
                 (
                     TileType::Grass,
-                    Some(EntityType::Building(Building::hut_uninitialized())),
+                    Some(EntityType::Building(Building::hut_uninitialized(0, config))),
                 ),
                 (
                     TileType::Grass,
                     Some(EntityType::Building(Building::Market)),
                 ),
-                // TODO IVO: This is an example of how to add resources to a biome
-                (
-                    TileType::Grass,
-                    Some(EntityType::Resource(Resource::new(
-                        ResourceItem::Berry,
-                        config.resource_amount_mean as u16,
-                    ))),
-                ),
-                (
-                    TileType::Grass,
-                    Some(EntityType::Resource(Resource::new(
-                        ResourceItem::Wheat,
-                        config.resource_amount_mean as u16,
-                    ))),
-                ),
-                (
-                    TileType::Grass,
-                    Some(EntityType::Resource(Resource::new(
-                        ResourceItem::Meat,
-                        config.resource_amount_mean as u16,
-                    ))),
-                ),
             ],
-            // TODO IVO: Don't forget to update the weights
-            weights: WeightedIndex::new(&[5000, 200, 30, 10, 30, 30, 30]).unwrap(),
-        }
+            vec![5000, 200, 30, 10],
+            config,
+        )
     }
 
-    pub fn high_lands() -> TileDistribution {
-        TileDistribution {
-            tiles: vec![
+    pub fn high_lands(config: &Config) -> TileDistribution {
+        TileDistribution::new(
+            BiomeKind::HighLands,
+            vec![
                 (TileType::Dirt, None),
                 (TileType::DirtRock, None),
                 (TileType::DirtTreeDead, None),
                 (
                     TileType::Dirt,
-                    Some(EntityType::Building(Building::hut_uninitialized())),
+                    Some(EntityType::Building(Building::hut_uninitialized(0, config))),
                 ),
             ],
-            weights: WeightedIndex::new(&[2000, 20, 10, 1]).unwrap(),
-        }
+            vec![2000, 20, 10, 1],
+            config,
+        )
     }
 
     pub fn ocean(config: &Config) -> TileDistribution {
-        TileDistribution {
-            tiles: vec![
-                (TileType::Water, None),
-                (TileType::WaterRock, None),
-                (
-                    TileType::Water,
-                    Some(EntityType::Resource(Resource::new(
-                        ResourceItem::Fish,
-                        config.resource_amount_mean as u16,
-                    ))),
-                ),
-            ],
-            weights: WeightedIndex::new(&[1000, 1, 20]).unwrap(),
-        }
+        TileDistribution::new(
+            BiomeKind::Ocean,
+            vec![(TileType::Water, None), (TileType::WaterRock, None)],
+            vec![1000, 1],
+            config,
+        )
     }
-    pub fn desert() -> TileDistribution {
-        TileDistribution {
-            tiles: vec![
+    pub fn desert(config: &Config) -> TileDistribution {
+        TileDistribution::new(
+            BiomeKind::Desert,
+            vec![
                 (TileType::Sand, None),
                 (TileType::SandRock, None),
                 (TileType::SandTreeDead, None),
                 (TileType::Sand, Some(EntityType::Building(Building::Market))),
             ],
-            weights: WeightedIndex::new(&[1000, 10, 15, 2]).unwrap(),
-        }
+            vec![1000, 10, 15, 2],
+            config,
+        )
     }
-    pub fn beach() -> TileDistribution {
-        TileDistribution {
-            tiles: vec![
+    pub fn beach(config: &Config) -> TileDistribution {
+        TileDistribution::new(
+            BiomeKind::Beach,
+            vec![
                 (TileType::Sand, None),
                 (TileType::SandPalm, None),
                 (TileType::SandTreeDead, None),
                 (
                     TileType::Sand,
-                    Some(EntityType::Building(Building::Boat { has_agent: false })),
+                    Some(EntityType::Building(Building::boat(None))),
                 ),
             ],
-            weights: WeightedIndex::new(&[1000, 20, 5, 15]).unwrap(),
-        }
+            vec![1000, 20, 5, 15],
+            config,
+        )
     }
 }
 
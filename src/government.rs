@@ -0,0 +1,266 @@
+//! The government collects taxes from the agents and redistributes the revenue
+//! according to the configured policy.
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    config::Config,
+    entity::{agent::Agent, resources::PerResource, Entity, EntityType},
+    market::Market,
+};
+
+/// What the government does with the cash in its treasury at the beginning of
+/// every day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Redistribution {
+    /// Keep all the revenue in the treasury.
+    None,
+    /// Split the treasury evenly among all alive agents.
+    BasicIncome,
+    /// Split the treasury evenly among the agents below the critical energy.
+    Starving,
+    /// Buy food on the market for the public granary, and hand it out to the
+    /// agents below the critical energy.
+    Granary,
+}
+
+/// The government is not an entity in the world, it only holds the treasury
+/// and the public granary.
+#[derive(Debug, Clone)]
+pub struct Government {
+    /// Cash in the treasury.
+    pub cash: u32,
+    /// Food bought with the treasury, waiting to be handed out.
+    pub granary: PerResource<u32>,
+    /// Total amount of taxes collected since the start of the simulation.
+    pub revenue: u64,
+    /// Total amount of cash paid out or spent on food since the start of the
+    /// simulation.
+    pub spent: u64,
+}
+
+impl Government {
+    /// An empty treasury and granary.
+    pub fn new(config: &Config) -> Government {
+        Government {
+            cash: 0,
+            granary: PerResource::new(0, config),
+            revenue: 0,
+            spent: 0,
+        }
+    }
+
+    /// Add collected tax to the treasury.
+    pub fn collect(&mut self, tax: u32) {
+        self.cash += tax;
+        self.revenue += tax as u64;
+    }
+
+    /// Collect the income and wealth taxes from all alive agents. This resets
+    /// the daily income of the agents, and should be called once per day.
+    pub fn collect_taxes(&mut self, entities: &mut [Entity], config: &Config) {
+        for e in entities {
+            if let EntityType::Agent(a) = &mut e.ty {
+                if a.dead {
+                    continue;
+                }
+                let tax = (a.income as f32 * config.income_tax) as u32
+                    + (a.cash as f32 * config.wealth_tax) as u32;
+                let tax = tax.min(a.cash);
+                a.cash -= tax;
+                a.income = 0;
+                self.collect(tax);
+            }
+        }
+    }
+
+    /// Spend the treasury according to the redistribution policy in the
+    /// config. This should be called once per day, after collecting taxes.
+    pub fn redistribute(
+        &mut self,
+        entities: &mut [Entity],
+        markets: &mut [Market],
+        config: &Config,
+    ) {
+        match config.redistribution {
+            Redistribution::None => {}
+            Redistribution::BasicIncome => self.pay_out(entities, |_| true),
+            Redistribution::Starving => self.pay_out(entities, |a| a.energy < a.critical_energy),
+            Redistribution::Granary => {
+                self.buy_food(entities, markets, config);
+                self.hand_out_food(entities, config);
+            }
+        }
+    }
+
+    /// Split the treasury evenly among all alive agents for which the filter
+    /// returns true.
+    fn pay_out(&mut self, entities: &mut [Entity], mut filter: impl FnMut(&Agent) -> bool) {
+        let mut is_recipient = |e: &Entity| match &e.ty {
+            EntityType::Agent(a) => !a.dead && filter(a),
+            _ => false,
+        };
+        let recipients = entities.iter().filter(|e| is_recipient(e)).count() as u32;
+        if recipients == 0 {
+            return;
+        }
+
+        let share = self.cash / recipients;
+        for e in entities.iter_mut() {
+            if !is_recipient(e) {
+                continue;
+            }
+            if let EntityType::Agent(a) = &mut e.ty {
+                a.cash += share;
+            }
+        }
+        self.cash -= share * recipients;
+        self.spent += (share * recipients) as u64;
+    }
+
+    /// Spend the treasury on the cheapest orders of every market, the budget
+    /// is split evenly across the kinds of food.
+    fn buy_food(&mut self, entities: &mut [Entity], markets: &mut [Market], config: &Config) {
+        let food = self
+            .granary
+            .keys()
+            .filter(|r| r.is_food(config))
+            .collect::<Vec<_>>();
+        if food.is_empty() {
+            return;
+        }
+        let budget = self.cash / food.len() as u32;
+        for r in food {
+            let mut budget_left = budget;
+            for market in markets.iter_mut() {
+                let amount = market.volume()[r];
+                if amount == 0 || budget_left == 0 {
+                    continue;
+                }
+                let (sellers, gained, tax) = market.buy(r, amount, budget_left);
+                for (agent, price) in sellers {
+                    if let EntityType::Agent(b) = &mut entities[agent.as_index()].ty {
+                        b.earn(price);
+                    }
+                    budget_left -= price;
+                    self.cash -= price;
+                    self.spent += price as u64;
+                }
+                // The sales tax would go right back into the treasury, so it is
+                // only taken from the budget.
+                budget_left -= tax;
+                self.granary[r] += gained;
+            }
+        }
+    }
+
+    /// Give every agent below the critical energy a ration of the food in the
+    /// granary, the ration is taken from the most stocked kind of food.
+    fn hand_out_food(&mut self, entities: &mut [Entity], config: &Config) {
+        for e in entities {
+            if let EntityType::Agent(a) = &mut e.ty {
+                if a.dead || a.energy >= a.critical_energy {
+                    continue;
+                }
+                let (item, stock) = match self
+                    .granary
+                    .iter()
+                    .filter(|(r, _)| r.is_food(config))
+                    .max_by_key(|(_, s)| **s)
+                {
+                    Some((r, s)) => (r, *s),
+                    None => return,
+                };
+                let ration = config.granary_ration.min(stock);
+                if ration == 0 {
+                    return;
+                }
+                self.granary[item] -= ration;
+                a.collect(item, ration);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::entity::{
+        resources::{Good, ResourceConfig, ResourceItem},
+        EntityId,
+    };
+    use crate::world::Pos;
+
+    /// The default resources and wood, which can not be eaten.
+    fn config() -> Config {
+        let mut config = Config {
+            granary_ration: 5,
+            ..Config::default()
+        };
+        config.resources.push(ResourceConfig {
+            name: "wood".to_string(),
+            nutrition: 0,
+            texture: 0,
+            spawns: Vec::new(),
+            job: None,
+            good: Good::Material,
+        });
+        config
+    }
+
+    fn agent(config: &Config) -> Entity {
+        let mut a = Agent::new(config);
+        a.energy = 0;
+        a.critical_energy = 100;
+        Entity {
+            pos: Pos::default(),
+            ty: EntityType::Agent(Box::new(a)),
+        }
+    }
+
+    #[test]
+    fn granary_only_buys_food() {
+        let config = config();
+        let wheat = ResourceItem::by_name("wheat", &config).unwrap();
+        let wood = ResourceItem::by_name("wood", &config).unwrap();
+        let mut market = Market::new(Rc::new(config.clone()));
+        market.order(EntityId::new(0), wood, 1, 50);
+        market.order(EntityId::new(0), wheat, 10, 10);
+        let mut markets = vec![market];
+        let mut entities = vec![agent(&config)];
+
+        let mut government = Government::new(&config);
+        government.cash = 400;
+        government.buy_food(&mut entities, &mut markets, &config);
+        assert_eq!(government.granary[wood], 0);
+        assert_eq!(government.granary[wheat], 10);
+        assert_eq!(markets[0].volume()[wood], 50);
+        assert_eq!(government.cash, 300);
+    }
+
+    #[test]
+    fn granary_hands_out_food() {
+        let config = config();
+        let wheat = ResourceItem::by_name("wheat", &config).unwrap();
+        let wood = ResourceItem::by_name("wood", &config).unwrap();
+        let mut government = Government::new(&config);
+        government.granary[wood] = 100;
+        government.granary[wheat] = 8;
+        let mut entities = vec![agent(&config), agent(&config)];
+
+        government.hand_out_food(&mut entities, &config);
+        let rations = entities
+            .iter()
+            .map(|e| match &e.ty {
+                EntityType::Agent(a) => (a.inventory[wheat], a.inventory[wood]),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rations[0].0 - rations[1].0, 2);
+        assert_eq!(rations[0].1, rations[1].1);
+        assert_eq!(government.granary[wood], 100);
+        assert_eq!(government.granary[wheat], 0);
+    }
+}
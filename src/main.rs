@@ -5,11 +5,14 @@ use dear_gui::AppInit;
 use glium::Surface;
 use rayon::prelude::*;
 
+pub mod bank;
 pub mod config;
 pub mod entity;
 pub mod generation;
+pub mod government;
 pub mod grid;
 pub mod market;
+pub mod price_index;
 pub mod statistics;
 pub mod tile;
 pub mod ui;
@@ -56,8 +59,7 @@ fn main() -> std::io::Result<()> {
         Opt::Interactive { config } => {
             // Load config or default
             let config = if let Some(path) = config {
-                let string = std::fs::read_to_string(path)?;
-                serde_json::from_str(&string)?
+                Config::load(&path)?
             } else {
                 Config::default()
             };
@@ -70,8 +72,7 @@ fn main() -> std::io::Result<()> {
             for f in std::fs::read_dir(configs)? {
                 let path = f?.path();
                 if let Some("json") = path.extension().and_then(|e| e.to_str()) {
-                    let string = std::fs::read_to_string(&path)?;
-                    let config: Config = serde_json::from_str(&string)?;
+                    let config = Config::load(&path)?;
 
                     let file_stem = path.file_stem().unwrap().to_str().expect("Invalid name");
 
@@ -102,7 +103,7 @@ pub fn batch(configs: Vec<(Config, PathBuf)>) {
         let config = Rc::new(config);
 
         // Statistics will be saved here
-        let stats = Rc::new(RefCell::new(Statistics::new()));
+        let stats = Rc::new(RefCell::new(Statistics::new(config.clone())));
 
         // Create the world using the config
         let mut world = World::new(
@@ -135,8 +136,8 @@ pub fn batch(configs: Vec<(Config, PathBuf)>) {
 pub fn interactive(config: Config) {
     let mut app = AppInit::new();
 
-    let stats = Rc::new(RefCell::new(Statistics::new()));
     let config = Rc::new(config);
+    let stats = Rc::new(RefCell::new(Statistics::new(config.clone())));
 
     // This ui wraps all the imgui code.
     let ui = Rc::new(RefCell::new(UI::new(app.imgui.clone(), stats.clone())));
@@ -2,34 +2,83 @@
 
 use std::rc::Rc;
 
+use serde::{Deserialize, Serialize};
+
 use crate::{
     config::Config,
     entity::{
         resources::{PerResource, ResourceItem},
         EntityId,
     },
+    price_index::PriceIndex,
 };
 
-/// The global market where agents can trade, this is not the building, seen on
-/// the map, it is the backend used by the buildings.
-#[derive(Debug, Clone, Default)]
+/// How orders on the market are matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum MarketClearing {
+    /// Purchases are matched immediately against the cheapest orders.
+    Continuous,
+    /// Purchases are collected as bids during the day and cleared once per day
+    /// at the closing time, at one uniform price per resource.
+    CallAuction,
+}
+
+/// The market where agents can trade, this is not the building, seen on the
+/// map, it is the backend used by the buildings. Depending on the config there
+/// is either one global market, or one market per building.
+#[derive(Debug, Clone)]
 pub struct Market {
-    /// A moving average of the sold orders.
+    /// The current value of the price index of every resource.
     pub market_price: PerResource<f32>,
+    /// Computes the market price from the trades, see price_index.rs.
+    price_index: PerResource<Box<dyn PriceIndex>>,
     /// This counts every day how much of each resource has been sold.
     pub market_demand: PerResource<u32>,
+    /// How much of each resource has been sold the previous day.
+    pub previous_demand: PerResource<u32>,
     /// This contains all the open orders for every resource. Sorted from
     /// cheapest to most expensive.
     orders: PerResource<Vec<Order>>,
+    /// The bids collected for the next call auction, unused with continuous
+    /// clearing.
+    bids: PerResource<Vec<Bid>>,
     /// Reference to the config.
     config: Rc<Config>,
 }
 
+/// Record a trade in the price index and update the market price. The market
+/// price stays at zero until the first trade of the resource.
+fn record_trade(index: &mut dyn PriceIndex, market_price: &mut f32, price: u32, amount: u32) {
+    index.record(price, amount);
+    if let Some(price) = index.price() {
+        *market_price = price;
+    }
+}
+
 impl Market {
+    /// Create an empty market using the given config.
+    pub fn new(config: Rc<Config>) -> Market {
+        Market {
+            market_price: PerResource::new(0., &config),
+            price_index: PerResource::new(config.price_index.build(&config), &config),
+            market_demand: PerResource::new(0, &config),
+            previous_demand: PerResource::new(0, &config),
+            orders: PerResource::new(Vec::new(), &config),
+            bids: PerResource::new(Vec::new(), &config),
+            config,
+        }
+    }
+
     /// Should be called every step, this will reset the demand and expire/update the orders
     pub fn step(&mut self, tick: u32, mut _expire: impl FnMut(&Order, ResourceItem)) {
         if tick % self.config.day_length == 0 {
-            self.market_demand = Default::default();
+            self.previous_demand = self.market_demand.take();
+            for (r, index) in self.price_index.iter_mut() {
+                index.new_day();
+                if let Some(price) = index.price() {
+                    self.market_price[r] = price;
+                }
+            }
         }
 
         for (_r, orders) in self.orders.iter_mut() {
@@ -98,8 +147,16 @@ impl Market {
         resource: ResourceItem,
         amount: u32,
         cash_available: u32,
-    ) -> (Vec<(EntityId, u32)>, u32) {
+    ) -> (Vec<(EntityId, u32)>, u32, u32) {
         let mut sellers: Vec<(EntityId, u32)> = Vec::new();
+        // The sales tax is taken from the revenue of the sellers.
+        let mut tax: u32 = 0;
+        let sales_tax = self.config.sales_tax;
+        let mut sell = |agent: EntityId, price: u32| {
+            let t = (price as f32 * sales_tax) as u32;
+            tax += t;
+            sellers.push((agent, price - t));
+        };
 
         let orders: &mut Vec<Order> = &mut self.orders[resource];
         // Variables keeping track of the amount left to fulfill the buy order and accumulated price
@@ -122,12 +179,15 @@ impl Market {
 
                 // Fulfill the order
                 let (m_p, demand) = order.fulfill(am_left);
-                sellers.push((order.agent, am_left * order.cached_price));
+                sell(order.agent, am_left * order.cached_price);
 
                 // Update the demand and market price of the resource
-                self.market_price[resource] = self.market_price[resource]
-                    * (1. - self.config.market_price_update)
-                    + m_p as f32 * self.config.market_price_update;
+                record_trade(
+                    &mut *self.price_index[resource],
+                    &mut self.market_price[resource],
+                    m_p,
+                    am_left,
+                );
                 self.market_demand[resource] = self.market_demand[resource].saturating_add(demand);
 
                 acc_price = acc_price.saturating_add(order.cached_price * am_left);
@@ -140,30 +200,36 @@ impl Market {
                 am_left = am_left.saturating_sub(order.amount);
                 acc_price = acc_price.saturating_add(order.cached_price * order.amount);
 
-                let (m_p, demand) = order.fulfill(order.amount);
-                sellers.push((order.agent, order.amount * order.cached_price));
+                // Take the filled amount before fulfill sets it to zero.
+                let filled = order.amount;
+                let (m_p, demand) = order.fulfill(filled);
+                sell(order.agent, filled * order.cached_price);
 
                 // Update the demand and market price of the resource
-                self.market_price[resource] = self.market_price[resource]
-                    * (1. - self.config.market_price_update)
-                    + m_p as f32 * self.config.market_price_update;
+                record_trade(
+                    &mut *self.price_index[resource],
+                    &mut self.market_price[resource],
+                    m_p,
+                    filled,
+                );
                 self.market_demand[resource] = self.market_demand[resource].saturating_add(demand);
             }
         }
 
         // If the order is fully fulfilled only the accumulated price is returned
         // Otherwise, the fulfilled amount is returned as well
-        (sellers, amount - am_left)
+        (sellers, amount - am_left, tax)
     }
 
     /// Buy all the available resources, this returns the amount of money owed
-    /// to which agent and the total resources gained.
+    /// to which agent, the total resources gained and the sales tax owed to
+    /// the government.
     pub fn buy(
         &mut self,
         resource: ResourceItem,
         amount: u32,
         cash_available: u32,
-    ) -> (Vec<(EntityId, u32)>, u32) {
+    ) -> (Vec<(EntityId, u32)>, u32, u32) {
         let result = self.buy_resource(resource, amount, cash_available);
 
         // Remove all orders where the amount is 0
@@ -173,6 +239,113 @@ impl Market {
         result
     }
 
+    /// Place a bid for the next call auction. The escrow is the cash the agent
+    /// already paid, it is refunded when the auction clears.
+    pub fn bid(&mut self, agent: EntityId, item: ResourceItem, limit: u32, amount: u32) {
+        self.bids[item].push(Bid {
+            agent,
+            limit,
+            amount,
+            escrow: limit * amount,
+        });
+    }
+
+    /// Remove the bids of the agents for which `keep` is false, for example
+    /// because they died before the auction cleared.
+    pub fn retain_bids(&mut self, mut keep: impl FnMut(EntityId) -> bool) {
+        for (_, bids) in self.bids.iter_mut() {
+            bids.retain(|b| keep(b.agent));
+        }
+    }
+
+    /// Clear the call auction of every resource. For every resource the price
+    /// is chosen where the most resources can be traded, the cheapest orders
+    /// and the highest bids are then filled at that price. Unfilled bids are
+    /// removed, unfilled orders stay on the market.
+    pub fn clear_auction(&mut self) -> AuctionResult {
+        let mut result = AuctionResult::default();
+        let sales_tax = self.config.sales_tax;
+
+        for r in self.orders.keys() {
+            let orders = &mut self.orders[r];
+            let mut bids = std::mem::take(&mut self.bids[r]);
+            bids.sort_by_key(|b| std::cmp::Reverse(b.limit));
+
+            // Find the clearing price, by trying every order and bid price.
+            let supply = |p: u32| -> u32 {
+                orders
+                    .iter()
+                    .take_while(|o| o.cached_price <= p)
+                    .map(|o| o.amount)
+                    .sum()
+            };
+            let demand = |p: u32| -> u32 {
+                bids.iter()
+                    .take_while(|b| b.limit >= p)
+                    .map(|b| b.amount)
+                    .sum()
+            };
+            let clearing = orders
+                .iter()
+                .map(|o| o.cached_price)
+                .chain(bids.iter().map(|b| b.limit))
+                .map(|p| (p, supply(p), demand(p)))
+                .filter(|&(_, s, d)| s.min(d) > 0)
+                .min_by_key(|&(p, s, d)| {
+                    // Maximize the volume, then minimize the imbalance, then
+                    // take the lowest price.
+                    (std::cmp::Reverse(s.min(d)), (s as i64 - d as i64).abs(), p)
+                });
+
+            let (price, quantity) = match clearing {
+                Some((p, s, d)) => (p, s.min(d)),
+                None => (0, 0),
+            };
+
+            // Fill the cheapest orders
+            let mut left = quantity;
+            for order in orders.iter_mut() {
+                if left == 0 {
+                    break;
+                }
+                let filled = order.amount.min(left);
+                order.fulfill(filled);
+                left -= filled;
+
+                let revenue = filled * price;
+                let tax = (revenue as f32 * sales_tax) as u32;
+                result.tax += tax;
+                result.revenue.push((order.agent, revenue - tax));
+            }
+            orders.retain(|o| o.amount > 0);
+
+            // Fill the highest bids and refund the rest of the escrow
+            let mut left = quantity;
+            for bid in bids {
+                let filled = bid.amount.min(left);
+                left -= filled;
+                result.purchases.push(AuctionPurchase {
+                    agent: bid.agent,
+                    item: r,
+                    amount: filled,
+                    refund: bid.escrow - filled * price,
+                });
+            }
+
+            if quantity > 0 {
+                record_trade(
+                    &mut *self.price_index[r],
+                    &mut self.market_price[r],
+                    price,
+                    quantity,
+                );
+                self.market_demand[r] = self.market_demand[r].saturating_add(quantity);
+            }
+        }
+
+        result
+    }
+
     /// Returns the current price estimation
     pub fn market_price(&self, resource_item: ResourceItem) -> u32 {
         self.market_price[resource_item] as u32
@@ -181,8 +354,8 @@ impl Market {
     /// This function gives an estimate of what it would cost to buy a set of resources.
     pub fn total_price(&self, meals: &PerResource<u32>) -> u32 {
         let mut sum: u32 = 0;
-        for r_item in ResourceItem::iterator() {
-            sum += self.market_price[*r_item] as u32 * meals[*r_item];
+        for r_item in meals.keys() {
+            sum += self.market_price[r_item] as u32 * meals[r_item];
         }
 
         sum
@@ -267,3 +440,126 @@ impl Order {
         (self.cached_price, self.amount)
     }
 }
+
+/// A bid for the call auction: an agent wants to buy up to amount resources
+/// for at most limit per unit.
+#[derive(Debug, Clone)]
+pub struct Bid {
+    /// The agent which placed the bid.
+    pub agent: EntityId,
+    /// The maximum price per unit.
+    pub limit: u32,
+    /// The amount of resources the agent wants to buy.
+    pub amount: u32,
+    /// The cash the agent paid when placing the bid.
+    pub escrow: u32,
+}
+
+/// The resources and cash one buyer gets from a call auction.
+#[derive(Debug, Clone)]
+pub struct AuctionPurchase {
+    pub agent: EntityId,
+    pub item: ResourceItem,
+    /// The amount of resources bought.
+    pub amount: u32,
+    /// The part of the escrow that was not spent.
+    pub refund: u32,
+}
+
+/// The outcome of clearing a call auction.
+#[derive(Debug, Clone, Default)]
+pub struct AuctionResult {
+    /// The cash every seller earned, after tax.
+    pub revenue: Vec<(EntityId, u32)>,
+    /// What every buyer got.
+    pub purchases: Vec<AuctionPurchase>,
+    /// The sales tax owed to the government.
+    pub tax: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn market(sales_tax: f32) -> (Market, ResourceItem) {
+        let config = Config {
+            sales_tax,
+            ..Config::default()
+        };
+        (Market::new(Rc::new(config)), ResourceItem::from_index(0))
+    }
+
+    #[test]
+    fn clear_auction_maximizes_volume() {
+        let (mut market, r) = market(0.25);
+        market.order(EntityId::new(0), r, 10, 2);
+        market.order(EntityId::new(1), r, 20, 3);
+        market.bid(EntityId::new(2), r, 25, 4);
+        market.bid(EntityId::new(3), r, 15, 2);
+
+        // At 20 four units trade, as many as at 25 but at a lower price.
+        let result = market.clear_auction();
+        assert_eq!(market.market_price(r), 20);
+
+        // The cheapest orders are filled at the clearing price, minus tax.
+        assert_eq!(
+            result.revenue,
+            vec![(EntityId::new(0), 30), (EntityId::new(1), 30)]
+        );
+        assert_eq!(result.tax, 20);
+        assert_eq!(market.orders[r].len(), 1);
+        assert_eq!(market.orders[r][0].amount, 1);
+
+        // The highest bid is filled and gets the difference to its limit
+        // back, the other bid gets its whole escrow back.
+        let purchases = result
+            .purchases
+            .iter()
+            .map(|p| (p.agent, p.amount, p.refund))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            purchases,
+            vec![(EntityId::new(2), 4, 20), (EntityId::new(3), 0, 30)]
+        );
+        assert!(market.bids[r].is_empty());
+    }
+
+    #[test]
+    fn clear_auction_without_overlap() {
+        let (mut market, r) = market(0.);
+        market.order(EntityId::new(0), r, 30, 5);
+        market.bid(EntityId::new(1), r, 10, 3);
+
+        let result = market.clear_auction();
+        assert!(result.revenue.is_empty());
+        assert_eq!(result.tax, 0);
+        assert_eq!(result.purchases.len(), 1);
+        assert_eq!(result.purchases[0].amount, 0);
+        assert_eq!(result.purchases[0].refund, 30);
+        assert_eq!(market.orders[r][0].amount, 5);
+        assert_eq!(market.market_price(r), 0);
+    }
+
+    #[test]
+    fn clear_auction_partially_fills_bids() {
+        let (mut market, r) = market(0.);
+        market.order(EntityId::new(0), r, 5, 3);
+        market.bid(EntityId::new(1), r, 8, 2);
+        market.bid(EntityId::new(2), r, 6, 2);
+
+        // At 5 and 6 three units trade, the lowest price is used.
+        let result = market.clear_auction();
+        assert_eq!(market.market_price(r), 5);
+        assert_eq!(result.revenue, vec![(EntityId::new(0), 15)]);
+        let filled = result
+            .purchases
+            .iter()
+            .map(|p| (p.agent, p.amount, p.refund))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            filled,
+            vec![(EntityId::new(1), 2, 6), (EntityId::new(2), 1, 7)]
+        );
+        assert!(market.orders[r].is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+//! A price index turns the trades on a market into the market price the agents
+//! see. Which index is used can be selected in the config.
+
+use std::{collections::VecDeque, fmt::Debug};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+
+/// Estimates the price of one resource from the trades on the market.
+pub trait PriceIndex: Debug {
+    /// Record a trade of the given amount at the given price per unit.
+    fn record(&mut self, price: u32, amount: u32);
+
+    /// This is called at the beginning of every day.
+    fn new_day(&mut self) {}
+
+    /// The current price estimate, None until the first trade.
+    fn price(&self) -> Option<f32>;
+
+    /// Clone this index into a box, this is needed to clone markets.
+    fn box_clone(&self) -> Box<dyn PriceIndex>;
+}
+
+impl Clone for Box<dyn PriceIndex> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// The different price indices that can be selected in the config.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum PriceIndexKind {
+    /// Exponential moving average, updated with `market_price_update` on every
+    /// fill. It starts at the price of the first fill.
+    Ema,
+    /// Volume weighted average price of the current day. Until the first trade
+    /// of a day the price of the previous day is used.
+    Vwap,
+    /// The price of the last fill.
+    LastTrade,
+    /// The median price of the last fills.
+    Median { window: usize },
+}
+
+impl PriceIndexKind {
+    /// Create a new price index of this kind.
+    pub fn build(&self, config: &Config) -> Box<dyn PriceIndex> {
+        match self {
+            PriceIndexKind::Ema => Box::new(Ema {
+                price: None,
+                update: config.market_price_update,
+            }),
+            PriceIndexKind::Vwap => Box::new(Vwap::default()),
+            PriceIndexKind::LastTrade => Box::new(LastTrade::default()),
+            PriceIndexKind::Median { window } => Box::new(Median {
+                window: *window,
+                prices: VecDeque::with_capacity(*window),
+            }),
+        }
+    }
+}
+
+/// See [`PriceIndexKind::Ema`].
+#[derive(Debug, Clone)]
+pub struct Ema {
+    price: Option<f32>,
+    update: f32,
+}
+
+impl PriceIndex for Ema {
+    fn record(&mut self, price: u32, _amount: u32) {
+        let price = price as f32;
+        self.price = Some(match self.price {
+            Some(p) => p * (1. - self.update) + price * self.update,
+            None => price,
+        });
+    }
+
+    fn price(&self) -> Option<f32> {
+        self.price
+    }
+
+    fn box_clone(&self) -> Box<dyn PriceIndex> {
+        Box::new(self.clone())
+    }
+}
+
+/// See [`PriceIndexKind::Vwap`].
+#[derive(Debug, Clone, Default)]
+pub struct Vwap {
+    /// Sum of price times amount of all trades today.
+    turnover: u64,
+    /// Sum of the amounts of all trades today.
+    volume: u64,
+    /// Price of the previous day.
+    previous: Option<f32>,
+}
+
+impl PriceIndex for Vwap {
+    fn record(&mut self, price: u32, amount: u32) {
+        self.turnover += price as u64 * amount as u64;
+        self.volume += amount as u64;
+    }
+
+    fn new_day(&mut self) {
+        self.previous = self.price();
+        self.turnover = 0;
+        self.volume = 0;
+    }
+
+    fn price(&self) -> Option<f32> {
+        if self.volume == 0 {
+            self.previous
+        } else {
+            Some(self.turnover as f32 / self.volume as f32)
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn PriceIndex> {
+        Box::new(self.clone())
+    }
+}
+
+/// See [`PriceIndexKind::LastTrade`].
+#[derive(Debug, Clone, Default)]
+pub struct LastTrade {
+    price: Option<u32>,
+}
+
+impl PriceIndex for LastTrade {
+    fn record(&mut self, price: u32, _amount: u32) {
+        self.price = Some(price);
+    }
+
+    fn price(&self) -> Option<f32> {
+        self.price.map(|p| p as f32)
+    }
+
+    fn box_clone(&self) -> Box<dyn PriceIndex> {
+        Box::new(self.clone())
+    }
+}
+
+/// See [`PriceIndexKind::Median`].
+#[derive(Debug, Clone)]
+pub struct Median {
+    window: usize,
+    /// The prices of the last fills, the newest one is at the back.
+    prices: VecDeque<u32>,
+}
+
+impl PriceIndex for Median {
+    fn record(&mut self, price: u32, _amount: u32) {
+        if self.prices.len() >= self.window.max(1) {
+            self.prices.pop_front();
+        }
+        self.prices.push_back(price);
+    }
+
+    fn price(&self) -> Option<f32> {
+        if self.prices.is_empty() {
+            return None;
+        }
+        let mut sorted = self.prices.iter().copied().collect::<Vec<_>>();
+        sorted.sort_unstable();
+        let mid = sorted.len() / 2;
+        if sorted.len() % 2 == 0 {
+            Some((sorted[mid - 1] as f32 + sorted[mid] as f32) / 2.)
+        } else {
+            Some(sorted[mid] as f32)
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn PriceIndex> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ema_starts_at_first_fill() {
+        let mut index = Ema {
+            price: None,
+            update: 0.5,
+        };
+        assert_eq!(index.price(), None);
+        index.record(100, 1);
+        assert_eq!(index.price(), Some(100.));
+        index.record(50, 10);
+        assert_eq!(index.price(), Some(75.));
+        index.new_day();
+        assert_eq!(index.price(), Some(75.));
+    }
+
+    #[test]
+    fn vwap_weights_by_amount_and_keeps_previous_day() {
+        let mut index = Vwap::default();
+        assert_eq!(index.price(), None);
+        index.record(10, 3);
+        index.record(20, 1);
+        assert_eq!(index.price(), Some(12.5));
+
+        // Without trades the price of the previous day is used.
+        index.new_day();
+        assert_eq!(index.price(), Some(12.5));
+        index.record(30, 2);
+        assert_eq!(index.price(), Some(30.));
+        index.new_day();
+        index.new_day();
+        assert_eq!(index.price(), Some(30.));
+    }
+
+    #[test]
+    fn last_trade_is_last_fill() {
+        let mut index = LastTrade::default();
+        assert_eq!(index.price(), None);
+        index.record(7, 100);
+        index.record(3, 1);
+        assert_eq!(index.price(), Some(3.));
+    }
+
+    #[test]
+    fn median_of_window() {
+        let mut index = Median {
+            window: 3,
+            prices: VecDeque::new(),
+        };
+        assert_eq!(index.price(), None);
+        index.record(10, 1);
+        assert_eq!(index.price(), Some(10.));
+        index.record(20, 1);
+        assert_eq!(index.price(), Some(15.));
+        index.record(90, 1);
+        assert_eq!(index.price(), Some(20.));
+
+        // The oldest fill drops out of the window.
+        index.record(1, 1);
+        index.record(2, 1);
+        assert_eq!(index.price(), Some(2.));
+    }
+
+    #[test]
+    fn median_with_empty_window_keeps_last_fill() {
+        let mut index = Median {
+            window: 0,
+            prices: VecDeque::new(),
+        };
+        index.record(5, 1);
+        index.record(8, 1);
+        assert_eq!(index.price(), Some(8.));
+    }
+}
@@ -4,15 +4,21 @@
 use std::{
     io::{Result, Write},
     path::Path,
+    rc::Rc,
 };
 
 use crate::{
+    config::Config,
     entity::{
-        agent::{Agent, Job},
-        resources::PerResource,
-        Entity,
+        agent::{Activity, Agent, DeathCause, Job},
+        brain::BrainKind,
+        learning::JobValues,
+        resources::{PerResource, ResourceItem},
+        skill::Skills,
+        Entity, EntityId,
     },
-    world::World,
+    generation::BiomeKind,
+    world::{Pos, World},
 };
 
 /// Saves statistics of a simulation, this is used to display graphs in the
@@ -27,78 +33,342 @@ pub struct Statistics {
     pub agent_count: Vec<f32>,
     /// Average greed of the alive agents per tick
     pub agent_greed: Vec<f32>,
-    /// Distribution of jobs of alive agents
-    pub job_counts: [Vec<f32>; 5],
-    /// For every agent save the greed value and the time of death.
-    pub agents: Vec<Option<(u32, u32)>>,
+    /// Average age in days of the alive agents per tick
+    pub agent_age: Vec<f32>,
+    /// Average skill in their current job of the alive agents that are not
+    /// exploring per tick
+    pub agent_skill: Vec<f32>,
+    /// Count of the alive agents that are not exploring in the current tick.
+    working: f32,
+    /// Distribution of jobs of alive agents, the explorers first and then
+    /// the jobs in the order of Job::choices.
+    pub job_counts: Vec<Vec<f32>>,
+    /// How far the distribution of the harvesting jobs is from the relative
+    /// prices of the resources they harvest per tick, this is zero if the
+    /// share of every job equals the share of its resources in the sum of the
+    /// prices, and one if they do not overlap at all.
+    pub job_price_gap: Vec<f32>,
+    /// Total energy spent by the alive agents per tick, for every activity in
+    /// the order of Activity::ALL. Negative if the agents regenerate energy.
+    pub energy_spent: Vec<Vec<f32>>,
+    /// Cash in the government treasury per tick
+    pub treasury: Vec<f32>,
+    /// Total taxes collected until this tick
+    pub tax_revenue: Vec<f32>,
+    /// Total cash redistributed or spent by the government until this tick
+    pub redistributed: Vec<f32>,
+    /// Total cash deposited at the bank by alive agents per tick
+    pub deposits: Vec<f32>,
+    /// Total amount owed to the bank by alive agents per tick
+    pub loans: Vec<f32>,
+    /// Count of alive agents with a loan per tick
+    pub loan_count: Vec<f32>,
+    /// Total count of defaulted loans until this tick
+    pub defaults: Vec<f32>,
+    /// Total count of agents born until this tick
+    pub births: Vec<f32>,
+    /// Total count of buildings constructed until this tick
+    pub constructed: Vec<f32>,
+    /// Count of gossip messages exchanged per tick
+    pub messages: Vec<f32>,
+    /// Count of remembered resources and markets learned through gossip per
+    /// tick
+    pub gossip_learned: Vec<f32>,
+    /// Average amount of agents the learned information passed through per
+    /// tick
+    pub gossip_hops: Vec<f32>,
+    /// Average distance between the learning agent and the position of the
+    /// learned information per tick
+    pub gossip_distance: Vec<f32>,
+    /// For every agent save the greed value, the brain and the time of death.
+    pub agents: Vec<Option<AgentRecord>>,
+    /// Position of every market building.
+    pub market_positions: Vec<Pos>,
+    /// Average amount of residents of the inhabited huts, recorded once per
+    /// day and repeated for every tick of that day
+    pub household_size: Vec<f32>,
+    /// The households of all huts at the beginning of the last day.
+    pub households: Vec<HouseholdRecord>,
+    /// Prices of every local market, recorded once per day. Empty if local
+    /// markets are disabled.
+    pub market_prices: Vec<Vec<PerResource<f32>>>,
+    /// Amount of every resource sold on every local market per day. Empty if
+    /// local markets are disabled.
+    pub market_sales: Vec<Vec<PerResource<u32>>>,
+    /// Every move of an agent from one hut to another.
+    pub migrations: Vec<Migration>,
+    /// Reference to the config.
+    config: Rc<Config>,
 }
 
 impl Statistics {
-    pub fn new() -> Statistics {
+    pub fn new(config: Rc<Config>) -> Statistics {
         Statistics {
-            prices: Default::default(),
-            volume: Default::default(),
+            prices: PerResource::new(Vec::new(), &config),
+            volume: PerResource::new(Vec::new(), &config),
             agent_count: Default::default(),
             // agent greed and job counts should have one zero element
             agent_greed: vec![0.0],
-            job_counts: [vec![0.0], vec![0.0], vec![0.0], vec![0.0], vec![0.0]],
+            agent_age: vec![0.0],
+            agent_skill: vec![0.0],
+            working: 0.,
+            job_counts: vec![vec![0.0]; Job::choices().len() + 1],
+            energy_spent: vec![vec![0.0]; Activity::ALL.len()],
+            job_price_gap: Vec::new(),
+            treasury: Vec::new(),
+            tax_revenue: Vec::new(),
+            redistributed: Vec::new(),
+            deposits: vec![0.0],
+            loans: vec![0.0],
+            loan_count: vec![0.0],
+            defaults: Vec::new(),
+            births: Vec::new(),
+            constructed: Vec::new(),
+            messages: vec![0.0],
+            gossip_learned: vec![0.0],
+            gossip_hops: vec![0.0],
+            gossip_distance: vec![0.0],
             agents: Vec::new(),
+            market_positions: Vec::new(),
+            household_size: Vec::new(),
+            households: Vec::new(),
+            market_prices: Vec::new(),
+            market_sales: Vec::new(),
+            migrations: Vec::new(),
+            config,
         }
     }
 
     /// Call this to initialize the greed of the agents.
     pub fn init_agents(&mut self, entities: &[Entity]) {
-        self.agents = entities
-            .iter()
-            .map(|a| match &a.ty {
-                crate::entity::EntityType::Agent(a) => Some((a.greed, 0)),
-                crate::entity::EntityType::Resource(_) => None,
-                crate::entity::EntityType::Building(_) => None,
-            })
-            .collect();
+        self.agents.clear();
+        for e in entities {
+            self.add_entity(e, 0);
+        }
+    }
+
+    /// Call this for every entity added during the simulation, so the agents
+    /// stay indexed by their entity id.
+    pub fn add_entity(&mut self, e: &Entity, tick: u32) {
+        self.agents.push(match &e.ty {
+            crate::entity::EntityType::Agent(a) => Some(AgentRecord {
+                greed: a.greed,
+                brain: a.brain,
+                birth: tick,
+                home: a.home,
+                death: 0,
+                cause: None,
+                age: 0,
+                parent: a.parent,
+                generation: a.generation,
+                job_values: a.job_values.clone(),
+                skills: a.skills.clone(),
+            }),
+            crate::entity::EntityType::Resource(_) => None,
+            crate::entity::EntityType::Building(_) => None,
+        });
+    }
+
+    /// Call this to save the positions of the market buildings.
+    pub fn init_markets(&mut self, positions: &[Pos]) {
+        self.market_positions = positions.to_vec();
+    }
+
+    /// Call this when the agent at the given index moved from one hut to
+    /// another.
+    pub fn add_migration(&mut self, agent: usize, from: Pos, to: Pos, world: &World) {
+        self.migrations.push(Migration {
+            tick: world.tick,
+            agent,
+            from,
+            to,
+            from_biome: world.biome(from),
+            to_biome: world.biome(to),
+        });
+    }
+
+    /// Call this for every market building constructed during the simulation.
+    pub fn add_market(&mut self, pos: Pos) {
+        self.market_positions.push(pos);
     }
 
     /// This should be called once per step, to record the statistics
     pub fn step(&mut self, world: &World) {
-        let prices = &world.market.market_price;
+        // With local markets we record the average price of all markets that
+        // have traded before, and the total volume.
         for (r, p) in self.prices.iter_mut() {
-            p.push(prices[r]);
+            let traded = world
+                .markets
+                .iter()
+                .map(|m| m.market_price[r])
+                .filter(|p| *p > 0.)
+                .collect::<Vec<_>>();
+            if traded.is_empty() {
+                p.push(0.);
+            } else {
+                p.push(traded.iter().sum::<f32>() / traded.len() as f32);
+            }
         }
-        let volumes = world.market.volume();
+        let volumes = world.markets.iter().map(|m| m.volume()).collect::<Vec<_>>();
         for (r, v) in self.volume.iter_mut() {
-            v.push(volumes[r] as f32);
+            v.push(volumes.iter().map(|m| m[r]).sum::<u32>() as f32);
+        }
+        self.treasury.push(world.government.cash as f32);
+        self.tax_revenue.push(world.government.revenue as f32);
+        self.redistributed.push(world.government.spent as f32);
+        self.defaults.push(world.bank.defaults as f32);
+        self.births.push(world.born_count as f32);
+        self.constructed.push(world.constructed_count as f32);
+        self.deposits.push(0.);
+        self.loans.push(0.);
+        self.loan_count.push(0.);
+
+        if world.time_of_day() == 0 || self.household_size.is_empty() {
+            self.households = world
+                .households()
+                .map(|(pos, h)| HouseholdRecord {
+                    pos,
+                    residents: h.residents.len() as u32,
+                    storage: h.storage.iter().map(|(_, a)| *a).sum(),
+                    cash: h.cash,
+                })
+                .collect();
+            let inhabited = self.households.iter().filter(|h| h.residents > 0);
+            let (huts, residents) = inhabited.fold((0, 0), |(n, r), h| (n + 1, r + h.residents));
+            self.household_size
+                .push(residents as f32 / (huts as f32).max(1.));
+        } else {
+            let size = *self.household_size.last().unwrap();
+            self.household_size.push(size);
+        }
+
+        if world.config.local_markets && world.time_of_day() == 0 {
+            self.market_prices.push(
+                world
+                    .markets
+                    .iter()
+                    .map(|m| m.market_price.clone())
+                    .collect(),
+            );
+            self.market_sales.push(
+                world
+                    .markets
+                    .iter()
+                    .map(|m| m.market_demand.clone())
+                    .collect(),
+            );
         }
 
         // At the end/beginning of every step divide the sum by the total and
         // add a new element to the vector which is zero.
+        let learned = self.gossip_learned.last().unwrap().max(1.);
+        *self.gossip_hops.last_mut().unwrap() /= learned;
+        *self.gossip_distance.last_mut().unwrap() /= learned;
+        self.messages.push(0.);
+        self.gossip_learned.push(0.);
+        self.gossip_hops.push(0.);
+        self.gossip_distance.push(0.);
         self.agent_count.push(world.alive_count as f32);
         *self.agent_greed.last_mut().unwrap() /= world.alive_count as f32;
         self.agent_greed.push(0.0);
+        *self.agent_age.last_mut().unwrap() /= world.alive_count as f32;
+        self.agent_age.push(0.0);
+        *self.agent_skill.last_mut().unwrap() /= self.working.max(1.);
+        self.agent_skill.push(0.0);
+        self.working = 0.;
         for jc in &mut self.job_counts {
             *jc.last_mut().unwrap() /= world.alive_count as f32;
+        }
+        self.job_price_gap.push(self.job_price_gap());
+        for jc in &mut self.job_counts {
             jc.push(0.);
         }
+        for e in &mut self.energy_spent {
+            e.push(0.);
+        }
+    }
+
+    /// Half the sum of the absolute differences between the share of every
+    /// harvesting job among the harvesting agents and the share of the prices
+    /// of the resources the job harvests, in the current tick.
+    fn job_price_gap(&self) -> f32 {
+        let jobs = Job::choices();
+        let config = &self.config;
+        let harvesting = |i: usize| self.prices.keys().any(|r| jobs[i].harvests(r, config));
+        let workers = |i: usize| *self.job_counts[i + 1].last().unwrap();
+        let price = |i: usize| {
+            self.prices
+                .keys()
+                .filter(|r| jobs[i].harvests(*r, config))
+                .map(|r| *self.prices[r].last().unwrap())
+                .sum::<f32>()
+        };
+        let harvest_jobs = (0..jobs.len())
+            .filter(|i| harvesting(*i))
+            .collect::<Vec<_>>();
+        let total_workers = harvest_jobs.iter().map(|i| workers(*i)).sum::<f32>();
+        let total_price = harvest_jobs.iter().map(|i| price(*i)).sum::<f32>();
+        if total_workers == 0. || total_price == 0. {
+            return 0.;
+        }
+        harvest_jobs
+            .iter()
+            .map(|i| (workers(*i) / total_workers - price(*i) / total_price).abs())
+            .sum::<f32>()
+            / 2.
     }
 
     /// This should be called once per agent per tick, to record death and job
     /// distribution
-    pub fn step_agent(&mut self, a: &Agent, idx: usize) {
-        if a.dead && self.agents[idx].unwrap().1 == 0 {
+    pub fn step_agent(&mut self, a: &Agent, idx: usize, config: &Config) {
+        let record = self.agents[idx].as_mut().unwrap();
+        if a.dead && record.death == 0 {
             // the length of agent_count is equal to the current step
-            self.agents[idx].as_mut().unwrap().1 = self.agent_count.len() as u32;
+            record.death = self.agent_count.len() as u32;
+            record.cause = a.death_cause;
+            record.age = a.age;
+            record.skills = a.skills.clone();
+        }
+        // The skills are saved once per day.
+        if !a.dead && (self.agent_count.len() as u32).is_multiple_of(config.day_length) {
+            record.skills = a.skills.clone();
+        }
+        // The job values change at most once per day.
+        if record.job_values.counts != a.job_values.counts {
+            record.job_values = a.job_values.clone();
         }
         if !a.dead {
             *self.agent_greed.last_mut().unwrap() += a.greed as f32;
-            match a.job {
-                Job::Explorer { .. } => *self.job_counts[0].last_mut().unwrap() += 1.,
-                Job::Farmer => *self.job_counts[1].last_mut().unwrap() += 1.,
-                Job::Lumberer => *self.job_counts[2].last_mut().unwrap() += 1.,
-                Job::Fisher { .. } => *self.job_counts[3].last_mut().unwrap() += 1.,
-                Job::Butcher => *self.job_counts[4].last_mut().unwrap() += 1.,
+            *self.agent_age.last_mut().unwrap() += a.age_in_days(config);
+            if a.job.choice_index().is_some() {
+                *self.agent_skill.last_mut().unwrap() += a.skills.level(&a.job);
+                self.working += 1.;
+            }
+            *self.deposits.last_mut().unwrap() += a.deposit as f32;
+            if let Some(loan) = &a.loan {
+                *self.loans.last_mut().unwrap() += loan.owed as f32;
+                *self.loan_count.last_mut().unwrap() += 1.;
             }
+            let job = a.job.choice_index().map_or(0, |i| i + 1);
+            *self.job_counts[job].last_mut().unwrap() += 1.;
+            let (activity, cost) = a.activity;
+            *self.energy_spent[activity.index()].last_mut().unwrap() += cost as f32;
         }
     }
 
+    /// This should be called for every gossip message between two agents.
+    pub fn message(&mut self) {
+        *self.messages.last_mut().unwrap() += 1.;
+    }
+
+    /// This should be called for everything an agent learns through gossip,
+    /// with the amount of hops and the distance to the learned position.
+    pub fn learned(&mut self, hops: u32, distance: u32) {
+        *self.gossip_learned.last_mut().unwrap() += 1.;
+        *self.gossip_hops.last_mut().unwrap() += hops as f32;
+        *self.gossip_distance.last_mut().unwrap() += distance as f32;
+    }
+
     /// Export all the statistics to csv files
     pub fn export(&self, path: &Path) -> Result<()> {
         let mut steps_path = path.to_path_buf();
@@ -107,49 +377,328 @@ impl Statistics {
         let mut agents_path = path.to_path_buf();
         agents_path.set_extension("agents.csv");
 
+        let mut markets_path = path.to_path_buf();
+        markets_path.set_extension("markets.csv");
+
+        let mut migrations_path = path.to_path_buf();
+        migrations_path.set_extension("migrations.csv");
+
+        let mut generations_path = path.to_path_buf();
+        generations_path.set_extension("generations.csv");
+
+        let mut households_path = path.to_path_buf();
+        households_path.set_extension("households.csv");
+
+        let mut values_path = path.to_path_buf();
+        values_path.set_extension("job_values.csv");
+
         let mut dir_path = path.to_path_buf();
         dir_path.pop();
         std::fs::create_dir_all(dir_path)?;
 
         let mut file = std::fs::File::create(steps_path)?;
+        // The price and volume columns depend on the resources in the config.
+        let resource_columns = |prefix: &str| {
+            ResourceItem::iterator(&self.config)
+                .map(|r| format!("{}_{}", prefix, r.name(&self.config)))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let job_columns = |prefix: &str| {
+            Job::choices()
+                .iter()
+                .map(|j| format!("{}_{}", prefix, j.name()))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
         writeln!(
             &mut file,
-            "tick,agent_count,job_counts_explorer,job_counts_farmer,\
-            job_counts_lumberer,job_counts_fisher,job_counts_butcher,\
-            prices_wheat,prices_berry,prices_fish,prices_meat,volume_wheat,\
-            volume_berry,volume_fish,volume_meat,agent_greed"
+            "tick,agent_count,job_counts_explorer,{},\
+            {},{},agent_greed,treasury,\
+            tax_revenue,redistributed,deposits,loans,loan_count,defaults,births,\
+            agent_age,messages,gossip_learned,gossip_hops,gossip_distance,\
+            household_size,agent_skill,job_price_gap,constructed,{}",
+            job_columns("job_counts"),
+            resource_columns("prices"),
+            resource_columns("volume"),
+            Activity::ALL
+                .iter()
+                .map(|a| format!("energy_{}", a.name()))
+                .collect::<Vec<_>>()
+                .join(","),
         )?;
         for i in 0..self.agent_count.len() {
-            writeln!(
+            write!(&mut file, "{},{},", i, self.agent_count[i])?;
+            for jc in &self.job_counts {
+                write!(&mut file, "{},", jc[i])?;
+            }
+            for (_, prices) in self.prices.iter() {
+                write!(&mut file, "{},", prices[i])?;
+            }
+            for (_, volume) in self.volume.iter() {
+                write!(&mut file, "{},", volume[i])?;
+            }
+            write!(
                 &mut file,
-                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
-                i,
-                self.agent_count[i],
-                self.job_counts[0][i],
-                self.job_counts[1][i],
-                self.job_counts[2][i],
-                self.job_counts[3][i],
-                self.job_counts[4][i],
-                self.prices.wheat[i],
-                self.prices.berry[i],
-                self.prices.fish[i],
-                self.prices.meat[i],
-                self.volume.wheat[i],
-                self.volume.berry[i],
-                self.volume.fish[i],
-                self.volume.meat[i],
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
                 self.agent_greed[i],
+                self.treasury[i],
+                self.tax_revenue[i],
+                self.redistributed[i],
+                self.deposits[i],
+                self.loans[i],
+                self.loan_count[i],
+                self.defaults[i],
+                self.births[i],
+                self.agent_age[i],
+                self.messages[i],
+                self.gossip_learned[i],
+                self.gossip_hops[i],
+                self.gossip_distance[i],
+                self.household_size[i],
+                self.agent_skill[i],
+                self.job_price_gap[i],
+                self.constructed[i],
             )?;
+            for e in &self.energy_spent {
+                write!(&mut file, ",{}", e[i])?;
+            }
+            writeln!(&mut file)?;
         }
 
         let mut file = std::fs::File::create(agents_path)?;
-        writeln!(&mut file, "greed,lifetime")?;
-        for a in &self.agents {
+        writeln!(
+            &mut file,
+            "agent,greed,lifetime,brain,birth,parent,generation,cause,age,home_x,home_y,{}",
+            job_columns("skill")
+        )?;
+        for (i, a) in self.agents.iter().enumerate() {
             if let Some(a) = a {
-                writeln!(&mut file, "{},{}", a.0, a.1)?;
+                let lifetime = if a.death == 0 { 0 } else { a.death - a.birth };
+                // The initial agents have no parent, this is left empty.
+                let parent = a
+                    .parent
+                    .map(|p| p.as_index().to_string())
+                    .unwrap_or_default();
+                // The cause is left empty for agents that are still alive.
+                let cause = a.cause.map(|c| format!("{:?}", c)).unwrap_or_default();
+                let skills = a
+                    .skills
+                    .levels
+                    .iter()
+                    .map(|l| l.to_string())
+                    .collect::<Vec<_>>()
+                    .join(",");
+                writeln!(
+                    &mut file,
+                    "{},{},{},{:?},{},{},{},{},{},{},{},{}",
+                    i,
+                    a.greed,
+                    lifetime,
+                    a.brain,
+                    a.birth,
+                    parent,
+                    a.generation,
+                    cause,
+                    a.age,
+                    a.home.x,
+                    a.home.y,
+                    skills,
+                )?;
+            }
+        }
+
+        // The greed distribution of every generation.
+        let generations = self
+            .agents
+            .iter()
+            .flatten()
+            .map(|a| a.generation)
+            .max()
+            .unwrap_or(0);
+        let mut file = std::fs::File::create(generations_path)?;
+        writeln!(
+            &mut file,
+            "generation,agents,greed_mean,greed_sd,greed_min,greed_max"
+        )?;
+        for g in 0..=generations {
+            let greed = self
+                .agents
+                .iter()
+                .flatten()
+                .filter(|a| a.generation == g)
+                .map(|a| a.greed as f32)
+                .collect::<Vec<_>>();
+            if greed.is_empty() {
+                continue;
+            }
+            let n = greed.len() as f32;
+            let mean = greed.iter().sum::<f32>() / n;
+            let sd = (greed.iter().map(|x| (x - mean).powi(2)).sum::<f32>() / n).sqrt();
+            let min = greed.iter().cloned().fold(f32::INFINITY, f32::min);
+            let max = greed.iter().cloned().fold(0., f32::max);
+            writeln!(
+                &mut file,
+                "{},{},{},{},{},{}",
+                g,
+                greed.len(),
+                mean,
+                sd,
+                min,
+                max
+            )?;
+        }
+
+        // Only export the job values if agents learned something.
+        let learned = self
+            .agents
+            .iter()
+            .flatten()
+            .any(|a| a.job_values.counts.iter().any(|c| *c > 0));
+        if learned {
+            let mut file = std::fs::File::create(values_path)?;
+            writeln!(
+                &mut file,
+                "agent,greed,{},{}",
+                job_columns("value"),
+                job_columns("count")
+            )?;
+            for (i, a) in self.agents.iter().enumerate() {
+                if let Some(a) = a {
+                    let v = &a.job_values;
+                    let values = v.values.iter().map(|x| x.to_string());
+                    let counts = v.counts.iter().map(|x| x.to_string());
+                    writeln!(
+                        &mut file,
+                        "{},{},{}",
+                        i,
+                        a.greed,
+                        values.chain(counts).collect::<Vec<_>>().join(",")
+                    )?;
+                }
+            }
+        }
+
+        let mut file = std::fs::File::create(households_path)?;
+        writeln!(&mut file, "x,y,residents,storage,cash")?;
+        for h in &self.households {
+            writeln!(
+                &mut file,
+                "{},{},{},{},{}",
+                h.pos.x, h.pos.y, h.residents, h.storage, h.cash
+            )?;
+        }
+
+        if !self.migrations.is_empty() {
+            let mut file = std::fs::File::create(migrations_path)?;
+            writeln!(
+                &mut file,
+                "tick,agent,from_x,from_y,to_x,to_y,from_biome,to_biome"
+            )?;
+            for m in &self.migrations {
+                writeln!(
+                    &mut file,
+                    "{},{},{},{},{},{},{:?},{:?}",
+                    m.tick, m.agent, m.from.x, m.from.y, m.to.x, m.to.y, m.from_biome, m.to_biome
+                )?;
+            }
+        }
+
+        if !self.market_prices.is_empty() {
+            let mut file = std::fs::File::create(markets_path)?;
+            writeln!(
+                &mut file,
+                "day,market,x,y,{},{}",
+                resource_columns("prices"),
+                resource_columns("sold")
+            )?;
+            for (day, (markets, sales)) in self
+                .market_prices
+                .iter()
+                .zip(&self.market_sales)
+                .enumerate()
+            {
+                for (m, ((prices, sold), pos)) in markets
+                    .iter()
+                    .zip(sales)
+                    .zip(&self.market_positions)
+                    .enumerate()
+                {
+                    let prices = prices
+                        .iter()
+                        .map(|(_, p)| p.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    let sold = sold
+                        .iter()
+                        .map(|(_, s)| s.to_string())
+                        .collect::<Vec<_>>()
+                        .join(",");
+                    writeln!(
+                        &mut file,
+                        "{},{},{},{},{},{}",
+                        day, m, pos.x, pos.y, prices, sold
+                    )?;
+                }
             }
         }
 
         Ok(())
     }
 }
+
+/// The statistics saved for every agent.
+#[derive(Debug, Clone)]
+pub struct AgentRecord {
+    pub greed: u32,
+    pub brain: BrainKind,
+    /// The tick the agent was born, zero for the initial agents.
+    pub birth: u32,
+    /// The position of the hut of the agent, this identifies its household.
+    pub home: Pos,
+    /// The tick the agent died, or zero if it is still alive.
+    pub death: u32,
+    /// Why the agent died, None while it is alive.
+    pub cause: Option<DeathCause>,
+    /// The age of the agent in ticks when it died, the initial agents do not
+    /// start at age zero.
+    pub age: u32,
+    /// The parent of the agent, None for the initial agents.
+    pub parent: Option<EntityId>,
+    /// The generation of the agent, the initial agents are generation zero.
+    pub generation: u32,
+    /// The last learned job values.
+    pub job_values: JobValues,
+    /// The skills of the agent, saved once per day and when it dies.
+    pub skills: Skills,
+}
+
+/// The statistics saved for every household.
+#[derive(Debug, Clone)]
+pub struct HouseholdRecord {
+    /// Position of the hut.
+    pub pos: Pos,
+    /// Count of the alive residents.
+    pub residents: u32,
+    /// Total amount of resources in the common storage.
+    pub storage: u32,
+    /// Cash in the common pool.
+    pub cash: u32,
+}
+
+/// The statistics saved for every move of an agent to another hut.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    /// The tick the agent moved.
+    pub tick: u32,
+    /// Index of the agent.
+    pub agent: usize,
+    /// Position of the old hut.
+    pub from: Pos,
+    /// Position of the new hut.
+    pub to: Pos,
+    /// Biome of the old hut.
+    pub from_biome: BiomeKind,
+    /// Biome of the new hut.
+    pub to_biome: BiomeKind,
+}
@@ -8,7 +8,7 @@ use glium::Surface;
 use imgui::{im_str, Condition, PlotLines, Slider, Ui, Window};
 
 use crate::{
-    entity::EntityId,
+    entity::{building::Building, EntityId, EntityType},
     statistics::Statistics,
     world::{Pos, World},
 };
@@ -123,23 +123,38 @@ impl UI {
         Window::new(im_str!("Market"))
             .size([200., 200.], Condition::Once)
             .build(ui, || {
-                let prices = world
-                    .market
-                    .cheapest_prices()
-                    .map(|p| p.map(|p| p as f32).unwrap_or(f32::NAN));
-                ui.text(&format!("Prices: {:#?}", prices));
-                ui.text(&format!("M-Prices: {:#?}", world.market.market_price));
-                ui.text(&format!("M-Demand: {:#?}", world.market.market_demand));
+                // Show the selected market, or the first one.
+                let market = self
+                    .selected_entity
+                    .map(|e| world.entity(e))
+                    .filter(|e| matches!(e.ty, EntityType::Building(Building::Market)))
+                    .map(|e| world.market_at(e.pos))
+                    .or_else(|| world.markets.first());
+                if let Some(market) = market {
+                    let prices = market
+                        .cheapest_prices()
+                        .map(|p| p.map(|p| p as f32).unwrap_or(f32::NAN));
+                    let config = &world.config;
+                    ui.text(&format!("Prices: {:#?}", prices.named(config)));
+                    ui.text(&format!(
+                        "M-Prices: {:#?}",
+                        market.market_price.named(config)
+                    ));
+                    ui.text(&format!(
+                        "M-Demand: {:#?}",
+                        market.market_demand.named(config)
+                    ));
+                }
                 for (r, p) in self.stats.borrow().prices.iter() {
                     let values = p.as_ref(); //&p[p.len().max(1000) - 1000..];
-                    PlotLines::new(ui, &im_str!("Price {:?}", r), values)
+                    PlotLines::new(ui, &im_str!("Price {}", r.name(&world.config)), values)
                         .graph_size([0., 50.])
                         .scale_min(0.)
                         .build();
                 }
                 for (r, v) in self.stats.borrow().volume.iter() {
                     let values = v.as_ref(); //&v[v.len().max(1000) - 1000..];
-                    PlotLines::new(ui, &im_str!("Volume {:?}", r), values)
+                    PlotLines::new(ui, &im_str!("Volume {}", r.name(&world.config)), values)
                         .graph_size([0., 50.])
                         .scale_min(0.)
                         .build();
@@ -154,9 +169,10 @@ impl UI {
                 PlotLines::new(
                     ui,
                     &im_str!(
-                        "Alive agents:\n{}\nDead agents:\n{}",
+                        "Alive agents:\n{}\nDead agents:\n{}\nBorn agents:\n{}",
                         world.alive_count,
-                        world.start_count - world.alive_count
+                        world.start_count + world.born_count - world.alive_count,
+                        world.born_count
                     ),
                     self.stats.borrow().agent_count.as_ref(),
                 )
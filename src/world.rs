@@ -2,23 +2,27 @@
 //! This file ties together the simulation of agents, markets, etc. and performs
 //! some other tasks.
 
-use std::rc::Rc;
+use std::{collections::HashMap, rc::Rc};
 
 use dear_gui::graphics::primitives::{Sprite, Vf2};
 use glium::Display;
 use rand::{seq::IteratorRandom, thread_rng, Rng};
 
 use crate::{
+    bank::Bank,
     config::Config,
     entity::{agent::Job, Entity, EntityId, EntityType},
     entity::{
-        agent::{Agent, AgentAction},
+        agent::{Agent, AgentAction, AgentState},
         building::Building,
-        resources::Resource,
+        construction::{BuildingKind, Site},
+        household::Household,
+        resources::{Resource, ResourceItem},
     },
-    generation::BiomeMap,
+    generation::{BiomeKind, BiomeMap},
+    government::Government,
     grid::CanvasGrid,
-    market::Market,
+    market::{Market, MarketClearing},
     statistics::Statistics,
     tile::TileType,
 };
@@ -34,12 +38,21 @@ pub struct World {
     /// The tile entities of the entire map. The length is width x height. For
     /// more details see tile.rs
     pub tiles_entity: Vec<Option<EntityId>>,
+    /// The biome every tile was generated in. The length is width x height.
+    pub tiles_biome: Vec<BiomeKind>,
     /// All the entities are in this array. This is in one place to hopefully
-    /// improve cache locality. Entities do not get added or deleted after
-    /// initialization.
+    /// improve cache locality. Entities never get deleted, children, the huts
+    /// built for them and construction sites are added at the end.
     entities: Vec<Entity>,
-    /// The global market
-    pub market: Market,
+    /// The markets, this contains one global market, or if local markets are
+    /// enabled, one market per market building.
+    pub markets: Vec<Market>,
+    /// Maps the position of every market building to its index in markets.
+    pub market_ids: HashMap<Pos, usize>,
+    /// The government collecting taxes and holding the treasury.
+    pub government: Government,
+    /// The bank holding the deposits and lending to agents.
+    pub bank: Bank,
     /// Width of the world in tiles
     pub width: usize,
     /// Height of the world in tiles
@@ -55,6 +68,12 @@ pub struct World {
     pub alive_count: u32,
     /// Count of alive agents at the beginning of the simulation.
     pub start_count: u32,
+    /// Count of agents born during the simulation.
+    pub born_count: u32,
+    /// Count of buildings constructed by the agents.
+    pub constructed_count: u32,
+    /// Count of households, every new household gets the next id.
+    household_count: u32,
 }
 
 impl World {
@@ -70,33 +89,64 @@ impl World {
 
         let mut entities = Vec::new();
         let mut tiles_entity = vec![None; width * height];
-        let tiles_type = (0..width * height)
+        let mut household_count = 0;
+        let (tiles_type, tiles_biome) = (0..width * height)
             .map(|i| {
                 let pos = Pos::new((i % width) as i16, (i / width) as i16);
-                let (tt, e) = biomes.get(pos, rng);
+                let (biome, tt, e) = biomes.get(pos, rng);
                 if let Some(mut e) = e {
+                    // Number the households in the order of their huts.
+                    if let EntityType::Building(Building::Hut(h)) = &mut e {
+                        h.id = household_count;
+                        household_count += 1;
+                    }
                     e.initialize(pos, &mut entities, &config);
                     entities.push(Entity { pos, ty: e });
                     tiles_entity[i] = Some(EntityId::new(entities.len() - 1))
                 }
-                tt
+                (tt, biome)
             })
             .take(width * height)
-            .collect::<Vec<_>>();
+            .unzip();
 
         let start_count = entities
             .iter()
             .filter(|e| matches!(e.ty, EntityType::Agent(_)))
             .count() as u32;
 
+        // Create either one global market or one market per market building.
+        let market_positions = entities
+            .iter()
+            .filter(|e| matches!(e.ty, EntityType::Building(Building::Market)))
+            .map(|e| e.pos)
+            .collect::<Vec<_>>();
+        let market_count = if config.local_markets {
+            market_positions.len()
+        } else {
+            1
+        };
+        let markets = (0..market_count)
+            .map(|_| Market::new(config.clone()))
+            .collect();
+        let market_ids = market_positions
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (*p, if config.local_markets { i } else { 0 }))
+            .collect();
+
         // Initialize the stats agents
         stats.init_agents(&entities);
+        stats.init_markets(&market_positions);
 
         World {
             tiles_type,
             tiles_entity,
+            tiles_biome,
             entities,
-            market: Market::default(),
+            markets,
+            market_ids,
+            government: Government::new(&config),
+            bank: Bank::default(),
             width,
             height,
             dirty: true,
@@ -104,6 +154,9 @@ impl World {
             is_running: true,
             alive_count: 0,
             start_count,
+            born_count: 0,
+            constructed_count: 0,
+            household_count,
             config,
         }
     }
@@ -206,21 +259,46 @@ impl World {
         self.entities
             .iter()
             .enumerate()
-            .filter(|(_, e)| matches!(e.ty, EntityType::Agent(Agent { dead: false, .. })))
+            .filter(|(_, e)| matches!(&e.ty, EntityType::Agent(a) if !a.dead))
             .map(|(i, _)| EntityId::new(i))
             .choose(&mut thread_rng())
     }
 
     /// Step one step, regardless of the running flag.
     pub fn step_once(&mut self, stats: &mut Statistics) {
-        // Step the market, this resets the demand and recalculates the order prices.
+        // Step the markets, this resets the demand and recalculates the order prices.
         {
             let es = &mut self.entities;
-            self.market.step(self.tick, |o, r| {
-                if let EntityType::Agent(a) = &mut es[o.agent.as_index()].ty {
-                    a.collect(r, o.amount);
-                }
-            });
+            for market in &mut self.markets {
+                market.step(self.tick, |o, r| {
+                    if let EntityType::Agent(a) = &mut es[o.agent.as_index()].ty {
+                        a.collect(r, o.amount);
+                    }
+                });
+            }
+        }
+
+        // At the beginning of every day the bank collects the loan
+        // installments, and the government collects taxes and redistributes
+        // them.
+        if self.tick > 0 && self.time_of_day() == 0 {
+            if self.config.bank {
+                self.bank.step_day(&mut self.entities, &self.config);
+            }
+            self.government
+                .collect_taxes(&mut self.entities, &self.config);
+            self.government
+                .redistribute(&mut self.entities, &mut self.markets, &self.config);
+            if self.config.births {
+                self.births(stats);
+            }
+        }
+
+        // With call auctions, the markets clear at the closing time.
+        if self.config.market_clearing == MarketClearing::CallAuction
+            && self.time_of_day() == self.config.closing_time
+        {
+            self.clear_auctions();
         }
 
         // set the alive count to zero, we add one for every agent
@@ -232,9 +310,25 @@ impl World {
             let id = EntityId::new(i);
             match &mut entity.ty {
                 EntityType::Agent(a) => {
+                    let (count, markets, home) =
+                        (self.entities.len(), self.market_ids.len(), a.home);
                     self.step_agent(a, &mut entity.pos, id);
                     // record statistics for this agent
-                    stats.step_agent(&a, id.as_index());
+                    stats.step_agent(&a, id.as_index(), &self.config);
+                    // and for the boats, huts and construction sites it added, and
+                    // the market it finished.
+                    for e in &self.entities[count..] {
+                        stats.add_entity(e, self.tick);
+                    }
+                    if let (true, AgentState::Construct(_, p)) =
+                        (self.market_ids.len() > markets, &a.state)
+                    {
+                        stats.add_market(*p);
+                    }
+                    // and where it moved.
+                    if a.home != home {
+                        stats.add_migration(id.as_index(), home, a.home, self);
+                    }
                 }
                 EntityType::Resource(r) => {
                     self.step_resource(r, &mut entity.pos, i);
@@ -244,6 +338,10 @@ impl World {
             self.entities[i] = entity;
         }
 
+        if self.config.gossip {
+            self.gossip(stats);
+        }
+
         // increase tick counter.
         self.tick += 1;
 
@@ -288,7 +386,7 @@ impl World {
                 let building_entity_id = self.tiles_entity[current_tile_idx].unwrap();
                 let building_entity = &mut self.entities[building_entity_id.as_index()];
                 if let EntityType::Building(b) = &mut building_entity.ty {
-                    b.agent_leave(id);
+                    b.agent_leave(id, a, &self.config);
                 } else {
                     panic!("Not a building");
                 }
@@ -308,10 +406,29 @@ impl World {
                 let building_entity_id = self.tiles_entity[idx].unwrap();
                 let building_entity = &mut self.entities[building_entity_id.as_index()];
                 if let EntityType::Building(b) = &mut building_entity.ty {
-                    b.agent_enter(id);
+                    b.agent_enter(id, a, &self.config);
                 } else {
                     panic!("Not a building");
                 }
+
+                // Residents of the same hut avoid doing the same job.
+                if self.config.household_jobs && p == a.home {
+                    if let Some(h) = self.household(p) {
+                        let others = h
+                            .residents
+                            .iter()
+                            .filter(|r| **r != id)
+                            .filter_map(|r| match &self.entity(*r).ty {
+                                EntityType::Agent(o) => Some(o.job.clone()),
+                                _ => None,
+                            })
+                            .collect::<Vec<_>>();
+                        if let Some(job) = Household::coordinate_job(&a.job, &others, &self.config)
+                        {
+                            a.job = job;
+                        }
+                    }
+                }
             }
             AgentAction::EnterBoat(p) => {
                 assert!(!a.in_building);
@@ -323,8 +440,16 @@ impl World {
                 let idx = self.idx(p);
                 let boat_entity_id = self.tiles_entity[idx].unwrap();
                 let boat_entity = &mut self.entities[boat_entity_id.as_index()];
-                if let EntityType::Building(Building::Boat { has_agent }) = &mut boat_entity.ty {
+                if let EntityType::Building(Building::Boat {
+                    has_agent,
+                    trips,
+                    catch,
+                    ..
+                }) = &mut boat_entity.ty
+                {
                     *has_agent = true;
+                    *trips += 1;
+                    *catch = 0;
                 } else {
                     panic!("Not a boat");
                 }
@@ -345,16 +470,23 @@ impl World {
                 if let Job::Fisher { boat } = &mut a.job {
                     let b_id = boat.unwrap();
 
-                    // Modify building
+                    // Modify building, a worn out boat is left behind.
                     let boat_entity = &mut self.entities[b_id.as_index()];
-                    if let EntityType::Building(Building::Boat { has_agent }) = &mut boat_entity.ty
+                    let durability = self.config.boat_durability;
+                    if let EntityType::Building(Building::Boat {
+                        has_agent, trips, ..
+                    }) = &mut boat_entity.ty
                     {
-                        *has_agent = false;
+                        if durability == 0 || *trips < durability {
+                            *has_agent = false;
+                            boat_entity.pos = *pos;
+                            self.tiles_entity[current_tile_idx] = Some(b_id);
+                        } else {
+                            self.tiles_entity[current_tile_idx] = None;
+                        }
                     } else {
                         panic!("Not a boat");
                     }
-                    boat_entity.pos = *pos;
-                    self.tiles_entity[current_tile_idx] = Some(b_id);
 
                     // Modify agent entity
                     *pos = p;
@@ -364,22 +496,227 @@ impl World {
                 }
             }
             AgentAction::Farm(p) => {
+                // Young, old and unskilled agents sometimes fail to farm.
+                let productivity = a.productivity(&self.config);
+                if productivity < 1. && thread_rng().gen::<f32>() >= productivity {
+                    return;
+                }
+
+                // A tool sometimes gives an extra unit, but agents can not
+                // farm more than they can carry.
+                let mut units =
+                    (1 + a.use_tool(&self.config) as u32).min(a.free_capacity(&self.config));
+                // A boat does not hold more than its capacity.
+                if let Job::Fisher { boat: Some(b) } = a.job {
+                    if let EntityType::Building(Building::Boat { catch, .. }) =
+                        &self.entities[b.as_index()].ty
+                    {
+                        units = units.min(self.config.boat_capacity.saturating_sub(*catch));
+                    }
+                }
+
                 // Modify resource
                 let idx = self.idx(p);
                 let resource_entity_id = self.tiles_entity[idx].unwrap();
                 let resource_entity = &mut self.entities[resource_entity_id.as_index()];
-                let resource_farmed = if let EntityType::Resource(r) = &mut resource_entity.ty {
-                    r.farm()
+                let farmed = if let EntityType::Resource(r) = &mut resource_entity.ty {
+                    (0..units).filter_map(|_| r.farm()).collect::<Vec<_>>()
                 } else {
                     panic!("Not a resource {:?}", resource_entity);
                 };
 
                 // Modify agent entity
-                if let Some(resource) = resource_farmed {
-                    a.collect(resource, 1)
+                if let Some(resource) = farmed.first() {
+                    a.harvest(*resource, farmed.len() as u32);
+                    if let Job::Fisher { boat: Some(b) } = a.job {
+                        if let EntityType::Building(Building::Boat { catch, .. }) =
+                            &mut self.entities[b.as_index()].ty
+                        {
+                            *catch += farmed.len() as u32;
+                        }
+                    }
+                    if self.config.skills {
+                        a.skills.practice(&a.job, &self.config);
+                    }
+                }
+            }
+            AgentAction::Craft(i) => {
+                let recipe = &self.config.recipes[i];
+                // Young, old and unskilled agents sometimes fail to craft,
+                // cooking at home always works.
+                let at_work = a.job.crafts(recipe);
+                let productivity = a.productivity(&self.config);
+                if at_work && productivity < 1. && thread_rng().gen::<f32>() >= productivity {
+                    return;
+                }
+
+                for (r, amount) in recipe.inputs().iter() {
+                    a.inventory[r] -= amount;
+                }
+                a.collect(recipe.output(), recipe.amount);
+                if self.config.skills && at_work {
+                    a.skills.practice(&a.job, &self.config);
+                }
+            }
+            AgentAction::PlaceBoat(p) => {
+                assert!(self.tile_is_walkable(p));
+                a.inventory[ResourceItem::boat(&self.config).expect("No boats")] -= 1;
+
+                let idx = self.idx(p);
+                let owner = self.household(a.home).map(|h| h.id);
+                self.entities.push(Entity {
+                    pos: p,
+                    ty: EntityType::Building(Building::boat(owner)),
+                });
+                self.tiles_entity[idx] = Some(EntityId::new(self.entities.len() - 1));
+            }
+            AgentAction::Construct(i, p) => {
+                // Start a new construction site on a free tile.
+                let idx = self.idx(p);
+                if self.tiles_entity[idx].is_none() {
+                    let owner = self.household(a.home).expect("Not a hut").id;
+                    let site = Site::new(i, &self.config.blueprints[i], owner);
+                    self.entities.push(Entity {
+                        pos: p,
+                        ty: EntityType::Building(Building::Site(site)),
+                    });
+                    self.tiles_entity[idx] = Some(EntityId::new(self.entities.len() - 1));
+                }
+
+                // Bring the materials, and work once they are all there.
+                let site_id = self.tiles_entity[idx].unwrap();
+                let site_entity = &mut self.entities[site_id.as_index()];
+                let finished = if let EntityType::Building(Building::Site(s)) = &mut site_entity.ty
+                {
+                    if s.is_supplied() {
+                        s.work = s.work.saturating_sub(1);
+                    } else {
+                        for (r, missing) in s.missing.iter_mut() {
+                            let given = (*missing).min(a.inventory[r]);
+                            *missing -= given;
+                            a.inventory[r] -= given;
+                        }
+                    }
+                    (s.is_supplied() && s.work == 0).then_some((s.kind, s.owner))
+                } else {
+                    panic!("Not a construction site");
+                };
+                if let Some((kind, owner)) = finished {
+                    let new_household = self.household_count;
+                    if kind == BuildingKind::Hut {
+                        self.household_count += 1;
+                    }
+                    let site_entity = &mut self.entities[site_id.as_index()];
+                    site_entity.ty =
+                        EntityType::Building(kind.building(owner, new_household, &self.config));
+                    self.constructed_count += 1;
+                    if kind == BuildingKind::Market {
+                        self.add_market(p);
+                    }
+                }
+            }
+            AgentAction::Relocate(hut) => {
+                // Move out of the old hut.
+                let old_hut = self.tiles_entity[self.idx(a.home)];
+                let mut moved_out = None;
+                if let Some(hut_id) = old_hut {
+                    if let EntityType::Building(Building::Hut(h)) =
+                        &mut self.entities[hut_id.as_index()].ty
+                    {
+                        h.residents.retain(|r| *r != id);
+                        if h.residents.is_empty() {
+                            moved_out =
+                                Some((hut_id, h.storage.take(), std::mem::take(&mut h.cash)));
+                        }
+                    }
+                }
+
+                // Build a new hut if the tile is free, and move in.
+                let idx = self.idx(hut);
+                if self.tiles_entity[idx].is_none() {
+                    let hut_entity = Entity {
+                        pos: hut,
+                        ty: EntityType::Building(Building::hut_uninitialized(
+                            self.household_count,
+                            &self.config,
+                        )),
+                    };
+                    self.household_count += 1;
+                    self.entities.push(hut_entity);
+                    self.tiles_entity[idx] = Some(EntityId::new(self.entities.len() - 1));
+                }
+                let hut_id = self.tiles_entity[idx].unwrap();
+                let new_id = if let EntityType::Building(Building::Hut(h)) =
+                    &mut self.entities[hut_id.as_index()].ty
+                {
+                    h.residents.push(id);
+                    // The last resident takes the storage and the pooled cash
+                    // along, a household living there already shares them.
+                    if let Some((_, storage, cash)) = &moved_out {
+                        for (r, amount) in storage.iter() {
+                            h.storage[r] += amount;
+                        }
+                        h.cash += cash;
+                    }
+                    (h.residents.len() == 1).then_some(h.id)
+                } else {
+                    panic!("Not a hut");
+                };
+
+                // The last resident moving into an empty hut takes the
+                // household along, the empty hut gets the id of the new hut.
+                if let (Some((old, _, _)), Some(new_id)) = (moved_out, new_id) {
+                    if let EntityType::Building(Building::Hut(h)) =
+                        &mut self.entities[old.as_index()].ty
+                    {
+                        let old_id = std::mem::replace(&mut h.id, new_id);
+                        if let EntityType::Building(Building::Hut(h)) =
+                            &mut self.entities[hut_id.as_index()].ty
+                        {
+                            h.id = old_id;
+                        }
+                    }
+                }
+                a.home = hut;
+            }
+            AgentAction::Store(r, amount) => {
+                assert!(a.in_building && *pos == a.home);
+                let hut_id = self.tiles_entity[current_tile_idx].unwrap();
+                if let EntityType::Building(Building::Hut(h)) =
+                    &mut self.entities[hut_id.as_index()].ty
+                {
+                    h.storage[r] += amount;
+                    a.inventory[r] -= amount;
+                } else {
+                    panic!("Not a hut");
+                }
+            }
+            AgentAction::Retrieve(r, amount) => {
+                assert!(a.in_building && *pos == a.home);
+                let hut_id = self.tiles_entity[current_tile_idx].unwrap();
+                if let EntityType::Building(Building::Hut(h)) =
+                    &mut self.entities[hut_id.as_index()].ty
+                {
+                    h.storage[r] -= amount;
+                    a.inventory[r] += amount;
+                } else {
+                    panic!("Not a hut");
+                }
+            }
+            AgentAction::Consume(r, q) => {
+                // In a shared hut the food is taken from the common storage.
+                if a.in_building && *pos == a.home {
+                    let hut_id = self.tiles_entity[current_tile_idx].unwrap();
+                    if let EntityType::Building(Building::Hut(h)) =
+                        &mut self.entities[hut_id.as_index()].ty
+                    {
+                        let taken = h.storage[r].min(q);
+                        h.storage[r] -= taken;
+                        a.inventory[r] += taken;
+                    }
                 }
+                a.consume(r, q, &self.config)
             }
-            AgentAction::Consume(r, q) => a.consume(r, q, &self.config),
             AgentAction::MarketOrder {
                 item,
                 price,
@@ -388,13 +725,20 @@ impl World {
                 // Tke the resources and create an order on the market
                 let inventory = &mut a.inventory[item];
                 *inventory = inventory.checked_sub(amount).unwrap();
-                self.market.order(id, item, price, amount);
+                let market = self.market_id(*pos);
+                self.markets[market].order(id, item, price, amount);
             }
             AgentAction::MarketPurchase { item, amount } => {
-                let (agents, resources_gained) = self.market.buy(item, amount, a.cash);
+                let market = self.market_id(*pos);
+                let (agents, resources_gained, tax) =
+                    self.markets[market].buy(item, amount, a.cash);
                 // Collect the new resources
                 a.collect(item, resources_gained);
 
+                // Pay the sales tax
+                a.cash = a.cash.checked_sub(tax).unwrap();
+                self.government.collect(tax);
+
                 // Transfer money to all agents we bought resources from
                 for (agent, price) in agents {
                     a.cash = a.cash.checked_sub(price).unwrap();
@@ -404,33 +748,251 @@ impl World {
                         ..
                     } = &mut self.entities[agent.as_index()]
                     {
-                        b.cash += price as u32;
+                        b.earn(price);
                     } else {
                         panic!()
                     }
                 }
             }
+            AgentAction::MarketBid {
+                item,
+                price,
+                amount,
+            } => {
+                // Pay up front, the rest is refunded when the auction clears.
+                a.cash = a.cash.checked_sub(price * amount).unwrap();
+                let market = self.market_id(*pos);
+                self.markets[market].bid(id, item, price, amount);
+            }
+            AgentAction::BankDeposit(amount) => self.bank.deposit(a, amount),
+            AgentAction::BankWithdraw(amount) => self.bank.withdraw(a, amount),
+            AgentAction::Borrow(amount) => self.bank.lend(a, amount, &self.config),
             // Do nothing this step
             AgentAction::None => {}
-            AgentAction::Die => {
+            AgentAction::Die(cause) => {
+                a.dead = true;
+                a.death_cause = Some(cause);
                 if a.in_building {
                     // Leave the building before the agent dies
                     let building_entity_id = self.tiles_entity[current_tile_idx].unwrap();
                     let building_entity = &mut self.entities[building_entity_id.as_index()];
                     if let EntityType::Building(b) = &mut building_entity.ty {
-                        b.agent_leave(id);
+                        b.agent_leave(id, a, &self.config);
                     } else {
                         panic!("Not a building");
                     }
                 } else {
                     self.tiles_entity[current_tile_idx] = None;
                 }
-                a.dead = true;
+
+                // Move out of the hut, so it can be used by a child.
+                if let Some(hut_id) = self.tiles_entity[self.idx(a.home)] {
+                    if let EntityType::Building(Building::Hut(h)) =
+                        &mut self.entities[hut_id.as_index()].ty
+                    {
+                        h.residents.retain(|r| *r != id);
+                    }
+                }
                 *pos = Pos::new(-1, -1);
             }
         }
     }
 
+    /// Let every agent with enough energy and cash have a child, this is
+    /// called once per day. The child moves into the hut of the parent if the
+    /// household is not full, otherwise into an empty hut close to the home of
+    /// the parent, or a new hut is built next to it. If huts are constructed
+    /// by the agents, no child is born without an empty hut.
+    fn births(&mut self, stats: &mut Statistics) {
+        let radius = self.config.search_radius * self.config.search_radius;
+        let constructed_huts = self
+            .config
+            .blueprints
+            .iter()
+            .any(|b| b.building == BuildingKind::Hut);
+        for i in 0..self.entities.len() {
+            let home = match &self.entities[i].ty {
+                EntityType::Agent(a)
+                    if !a.dead
+                        && a.energy >= self.config.birth_energy
+                        && a.cash >= self.config.birth_cash =>
+                {
+                    a.home
+                }
+                _ => continue,
+            };
+
+            let household_size = self.config.household_size as usize;
+            let parent_hut = self
+                .household(home)
+                .filter(|h| h.residents.len() < household_size)
+                .map(|_| home);
+            let free_hut = self.find_entity_around(home, radius, |e| {
+                matches!(&e.ty, EntityType::Building(Building::Hut(h)) if h.residents.is_empty())
+            });
+            // With a hut blueprint new huts have to be constructed.
+            let hut = parent_hut.or(free_hut).or_else(|| {
+                if constructed_huts {
+                    return None;
+                }
+                self.find_tile_around(home, radius, |p| {
+                    self.tile_type(p) == TileType::Grass && self.tile_is_walkable(p)
+                })
+            });
+            if let Some(hut) = hut {
+                self.spawn(i, hut, stats);
+            }
+        }
+    }
+
+    /// Spawn a child of the agent at the given index in the hut at the given
+    /// position. If there is no hut at the position a new one is built.
+    fn spawn(&mut self, parent: usize, hut: Pos, stats: &mut Statistics) {
+        let mut child = match &self.entities[parent].ty {
+            EntityType::Agent(a) => a.child(EntityId::new(parent), &self.config),
+            _ => panic!("Not an agent"),
+        };
+        child.home = hut;
+        child.in_building = true;
+        child.state = AgentState::BeHome;
+
+        // The parent pays the energy cost and gives a share of its cash and
+        // inventory to the child.
+        if let EntityType::Agent(a) = &mut self.entities[parent].ty {
+            let share = self.config.birth_share;
+            a.energy = a.energy.saturating_sub(self.config.birth_cost);
+            let cash = (a.cash as f32 * share) as u32;
+            a.cash -= cash;
+            child.cash = cash;
+            child.cash_quota = cash;
+            for (r, amount) in a.inventory.iter_mut() {
+                let given = (*amount as f32 * share) as u32;
+                *amount -= given;
+                child.inventory[r] = given;
+            }
+        }
+
+        let idx = self.idx(hut);
+        if self.tiles_entity[idx].is_none() {
+            let hut_entity = Entity {
+                pos: hut,
+                ty: EntityType::Building(Building::hut_uninitialized(
+                    self.household_count,
+                    &self.config,
+                )),
+            };
+            self.household_count += 1;
+            stats.add_entity(&hut_entity, self.tick);
+            self.entities.push(hut_entity);
+            self.tiles_entity[idx] = Some(EntityId::new(self.entities.len() - 1));
+        }
+
+        // Move the child into the hut.
+        let id = EntityId::new(self.entities.len());
+        let hut_id = self.tiles_entity[idx].unwrap();
+        if let EntityType::Building(Building::Hut(h)) = &mut self.entities[hut_id.as_index()].ty {
+            h.residents.push(id);
+            h.enter(&mut child, &self.config);
+        } else {
+            panic!("Not a hut");
+        }
+
+        let child = Entity {
+            pos: hut,
+            ty: EntityType::Agent(Box::new(child)),
+        };
+        stats.add_entity(&child, self.tick);
+        self.entities.push(child);
+        self.born_count += 1;
+    }
+
+    /// Let agents exchange what they remember. Agents outside exchange with
+    /// every agent on an adjacent tile, agents in a market with one random
+    /// other agent in the same market.
+    fn gossip(&mut self, stats: &mut Statistics) {
+        let mut rng = thread_rng();
+        let mut pairs = Vec::new();
+        let mut markets: HashMap<Pos, Vec<usize>> = HashMap::new();
+        for (i, e) in self.entities.iter().enumerate() {
+            match &e.ty {
+                EntityType::Agent(a)
+                    if !a.dead && a.in_building && self.market_ids.contains_key(&e.pos) =>
+                {
+                    markets.entry(e.pos).or_default().push(i);
+                }
+                EntityType::Agent(a) if !a.dead && !a.in_building => {
+                    for n in self.neighbors(e.pos).iter() {
+                        if let Some(id) = self.tiles_entity[self.idx(*n)] {
+                            let j = id.as_index();
+                            if j > i && matches!(self.entities[j].ty, EntityType::Agent(_)) {
+                                pairs.push((i, j));
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+        for agents in markets.values() {
+            if agents.len() < 2 {
+                continue;
+            }
+            for i in agents {
+                let j = *agents.iter().filter(|j| *j != i).choose(&mut rng).unwrap();
+                pairs.push((*i, j));
+            }
+        }
+
+        for (i, j) in pairs {
+            if rng.gen::<f32>() >= self.config.gossip_chance {
+                continue;
+            }
+            stats.message();
+            for (listener, speaker) in [(i, j), (j, i)] {
+                let memory = match &self.entities[speaker].ty {
+                    EntityType::Agent(a) => a.memory.clone(),
+                    _ => unreachable!(),
+                };
+                let pos = self.entities[listener].pos;
+                let learned = match &mut self.entities[listener].ty {
+                    EntityType::Agent(a) => a.memory.learn(&memory, &self.config),
+                    _ => unreachable!(),
+                };
+                for (p, hops) in learned {
+                    stats.learned(hops, p.distance(pos, self));
+                }
+            }
+        }
+    }
+
+    /// Clear the call auctions of all markets, and hand out the resources and
+    /// cash to the agents.
+    fn clear_auctions(&mut self) {
+        let entities = &self.entities;
+        for market in &mut self.markets {
+            // Dead agents can no longer collect their purchases.
+            market.retain_bids(|agent| match &entities[agent.as_index()].ty {
+                EntityType::Agent(a) => !a.dead,
+                _ => false,
+            });
+        }
+        for market in &mut self.markets {
+            let result = market.clear_auction();
+            for (agent, revenue) in result.revenue {
+                if let EntityType::Agent(a) = &mut self.entities[agent.as_index()].ty {
+                    a.earn(revenue);
+                }
+            }
+            for p in result.purchases {
+                if let EntityType::Agent(a) = &mut self.entities[p.agent.as_index()].ty {
+                    a.collect(p.item, p.amount);
+                    a.cash += p.refund;
+                }
+            }
+            self.government.collect(result.tax);
+        }
+    }
+
     // This is called for every resource every tick
     fn step_resource(&mut self, r: &mut Resource, pos: &mut Pos, idx: usize) {
         let current_tile_idx = self.idx(*pos);
@@ -487,16 +1049,67 @@ impl World {
                 .map(|a| Sprite {
                     vertex: Vf2::new(a.pos.x as f32 * 10., a.pos.y as f32 * 10.),
                     size: Vf2::new(10., 10.),
-                    texture_index: a.texture(),
+                    texture_index: a.texture(&self.config),
                 }),
         )
     }
 
+    /// Add a newly constructed market building, with local markets it gets
+    /// its own market.
+    fn add_market(&mut self, pos: Pos) {
+        let id = if self.config.local_markets {
+            self.markets.push(Market::new(self.config.clone()));
+            self.markets.len() - 1
+        } else {
+            0
+        };
+        self.market_ids.insert(pos, id);
+    }
+
+    /// Get the index of the market backing the market building at the given
+    /// position.
+    pub fn market_id(&self, pos: Pos) -> usize {
+        *self
+            .market_ids
+            .get(&pos)
+            .unwrap_or_else(|| panic!("No market at {:?}", pos))
+    }
+
+    /// Get the market backing the market building at the given position.
+    pub fn market_at(&self, pos: Pos) -> &Market {
+        &self.markets[self.market_id(pos)]
+    }
+
     /// Get the tile type at a given position
     pub fn tile_type(&self, p: Pos) -> TileType {
         self.tiles_type[self.idx(p)]
     }
 
+    /// Get the biome at a given position
+    pub fn biome(&self, p: Pos) -> BiomeKind {
+        self.tiles_biome[self.idx(p)]
+    }
+
+    /// Iterate over the households of all huts, with the position of the hut.
+    pub fn households(&self) -> impl Iterator<Item = (Pos, &Household)> {
+        self.entities.iter().filter_map(|e| match &e.ty {
+            EntityType::Building(Building::Hut(h)) => Some((e.pos, h)),
+            _ => None,
+        })
+    }
+
+    /// Get the household of the hut at the given position, None if there is no
+    /// hut.
+    pub fn household(&self, pos: Pos) -> Option<&Household> {
+        match self.entity_at(pos) {
+            Some(Entity {
+                ty: EntityType::Building(Building::Hut(h)),
+                ..
+            }) => Some(h),
+            _ => None,
+        }
+    }
+
     /// Get the entity at a given position
     pub fn entity_at(&self, pos: Pos) -> Option<&Entity> {
         let e = self.tiles_entity[self.idx(pos)]?;
@@ -542,4 +1155,14 @@ impl Pos {
     pub fn is_adjacent(self, other: Pos) -> bool {
         (self.x - other.x).abs() <= 1 && (self.y - other.y).abs() <= 1
     }
+
+    /// The amount of steps it takes to walk from self to other, ignoring
+    /// obstacles. This takes the wrapping of the world into account.
+    pub fn distance(self, other: Pos, world: &World) -> u32 {
+        let dx = (self.x - other.x).unsigned_abs() as u32;
+        let dy = (self.y - other.y).unsigned_abs() as u32;
+        let dx = dx.min(world.width as u32 - dx);
+        let dy = dy.min(world.height as u32 - dy);
+        dx.max(dy)
+    }
 }